@@ -1,10 +1,85 @@
 use crate::genetic_algorigthm::{GenoSeq, GenomeKind};
+use crate::mino::MinoShape;
 use crate::{block::block_kind, game::*};
 
+// weights used when none are given: line, height max, height diff, dead space
+pub const DEFAULT_WEIGHT: GenoSeq = [100, 1, 10, 100];
+
+// Pick the best placement of the current mino.
+// It looks one mino ahead when the next minos are visible, and tries holding if allowed.
 pub fn eval(game: &Game, weight: &GenoSeq) -> Game {
+    let mut candidates = vec![game.clone()];
+    if game.options.hold && !game.holded {
+        let mut held = game.clone();
+        hold(&mut held);
+        candidates.push(held);
+    }
+
     // (Game, score)
     let mut elite = (game.clone(), 0f64);
 
+    for game in candidates {
+        for game in placements(&game) {
+            let score = score(&game, weight);
+            if elite.1 < score {
+                elite.0 = game;
+                elite.1 = score;
+            }
+        }
+    }
+    elite.0
+}
+
+// score of the game with its mino fixed, one mino ahead when the next minos are visible
+fn score(game: &Game, weight: &GenoSeq) -> f64 {
+    if game.options.next_length == 0 {
+        evaluate(&game.field, weight)
+    } else {
+        let mut next = game.clone();
+        next.mino = next.next.pop_front().unwrap();
+        next.pos = Position::init();
+        placements(&next)
+            .iter()
+            .map(|next| evaluate(&next.field, weight))
+            .fold(0f64, f64::max)
+    }
+}
+
+// The placement `eval` picks for the current mino, shown to a player as a hint and compared with
+// where the player places it
+pub struct Advice {
+    start: Game,
+    pub cells: Vec<(usize, usize)>,
+    pub hold: bool, // the pick is the mino which comes out of the hold
+    best: f64,
+}
+
+impl Advice {
+    pub fn new(game: &Game, weight: &GenoSeq) -> Advice {
+        let mut start = game.clone();
+        start.events.clear();
+        let pick = eval(&start, weight);
+        Advice {
+            cells: mino_cells(&pick.mino, &pick.pos),
+            hold: pick.holded && !start.holded,
+            best: score(&pick, weight),
+            start,
+        }
+    }
+
+    // how much lower the current mino placed at `pos` scores than the pick, 0 for the pick
+    pub fn loss(&self, mino: &MinoShape, pos: &Position, weight: &GenoSeq) -> f64 {
+        let mut game = self.start.clone();
+        game.mino = *mino;
+        game.pos = *pos;
+        fix_mino(&mut game);
+        (self.best - score(&game, weight)).max(0.0)
+    }
+}
+
+// every rotation and column the mino can be dropped to, fixed on the field
+fn placements(game: &Game) -> Vec<Game> {
+    let mut games = vec![];
     for rotate_count in 0..=3 {
         let mut game = game.clone();
         for _ in 0..=rotate_count {
@@ -22,57 +97,32 @@ pub fn eval(game: &Game, weight: &GenoSeq) -> Game {
             move_mino(&mut game, new_pos);
             hard_drop(&mut game);
             fix_mino(&mut game);
-
-            let mut next = game.clone();
-            next.mino = next.next.pop_front().unwrap();
-            next.pos = Position::init();
-            for rotate_count in 0..=3 {
-                let mut next = next.clone();
-                for _ in 0..=rotate_count {
-                    rotate_right(&mut next);
-                }
-                for dx in -4..=5 {
-                    let mut next = next.clone();
-                    let new_pos = Position {
-                        x: match next.pos.x as isize + dx {
-                            (..=0) => 0,
-                            x => x as usize,
-                        },
-                        y: next.pos.y + 1,
-                    };
-                    move_mino(&mut next, new_pos);
-                    hard_drop(&mut next);
-                    fix_mino(&mut next);
-
-                    let line = erase_line_count(&next.field);
-                    let height_max = field_height_max(&next.field);
-                    let height_diff = diff_in_height(&next.field);
-                    let dead_space = dead_space_count(&next.field);
-
-                    // normalization
-                    let mut line = normalization(line as f64, 0.0, 4.0);
-                    let mut height_max = 1.0 - normalization(height_max as f64, 0.0, 20.0);
-                    let mut height_diff = 1.0 - normalization(height_diff as f64, 0.0, 200.0);
-                    let mut dead_space = 1.0 - normalization(dead_space as f64, 0.0, 200.0);
-
-                    // add weights
-                    line *= weight[GenomeKind::Line] as f64;
-                    height_max *= weight[GenomeKind::HeightMax] as f64;
-                    height_diff *= weight[GenomeKind::HeightDiff] as f64;
-                    dead_space *= weight[GenomeKind::DeadSpace] as f64;
-
-                    // calculate score
-                    let score = line + height_max + height_diff + dead_space;
-
-                    if elite.1 < score {
-                        elite.0 = game.clone();
-                        elite.1 = score;
-                    }
-                }
-            }
+            games.push(game);
         }
     }
-    elite.0
+    games
+}
+
+fn evaluate(field: &FieldSize, weight: &GenoSeq) -> f64 {
+    let line = erase_line_count(field);
+    let height_max = field_height_max(field);
+    let height_diff = diff_in_height(field);
+    let dead_space = dead_space_count(field);
+
+    // normalization
+    let mut line = normalization(line as f64, 0.0, 4.0);
+    let mut height_max = 1.0 - normalization(height_max as f64, 0.0, 20.0);
+    let mut height_diff = 1.0 - normalization(height_diff as f64, 0.0, 200.0);
+    let mut dead_space = 1.0 - normalization(dead_space as f64, 0.0, 200.0);
+
+    // add weights
+    line *= weight[GenomeKind::Line] as f64;
+    height_max *= weight[GenomeKind::HeightMax] as f64;
+    height_diff *= weight[GenomeKind::HeightDiff] as f64;
+    dead_space *= weight[GenomeKind::DeadSpace] as f64;
+
+    // calculate score
+    line + height_max + height_diff + dead_space
 }
 
 #[allow(clippy::needless_range_loop)]
@@ -127,6 +177,7 @@ pub fn diff_in_height(field: &FieldSize) -> usize {
     diff
 }
 
+#[allow(clippy::needless_range_loop)]
 pub fn dead_space_count(field: &FieldSize) -> usize {
     let mut count = 0;
     for y in (1..FIELD_HEIGHT - 2).rev() {
@@ -143,3 +194,31 @@ pub fn dead_space_count(field: &FieldSize) -> usize {
     }
     count
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_advice() {
+        let game = Game::with_options(Options {
+            seed: Some(1),
+            hold: false,
+            ..Default::default()
+        });
+        let advice = Advice::new(&game, &DEFAULT_WEIGHT);
+        assert!(!advice.hold);
+        let pick = eval(&game, &DEFAULT_WEIGHT);
+        assert_eq!(advice.cells, mino_cells(&pick.mino, &pick.pos));
+        assert_eq!(advice.loss(&pick.mino, &pick.pos, &DEFAULT_WEIGHT), 0.0);
+
+        // standing on end at the left wall is worse than the pick
+        let mut game = game;
+        rotate_right(&mut game);
+        for _ in 0..5 {
+            handle_input(&mut game, Input::Left).unwrap();
+        }
+        let pos = ghost_pos(&game.field, &game.pos, &game.mino);
+        assert!(advice.loss(&game.mino, &pos, &DEFAULT_WEIGHT) > 0.0);
+    }
+}
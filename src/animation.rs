@@ -0,0 +1,173 @@
+use crate::block::block_kind;
+use crate::game::*;
+use crate::mino::MinoShape;
+
+// durations in frames
+const LINE_FLASH_FRAMES: u32 = 12;
+const LINE_FLASH_BLINK: u32 = 3;
+const LOCK_FLASH_FRAMES: u32 = 4;
+const BANNER_FRAMES: u32 = 60;
+const GAMEOVER_ROW_FRAMES: u32 = 2;
+
+const BANNER_ROW: usize = 21;
+
+enum Effect {
+    // cleared rows blink before they collapse
+    LineFlash {
+        field: Box<FieldSize>,
+        rows: Vec<usize>,
+    },
+    // the mino just fixed lights up
+    LockFlash {
+        mino: MinoShape,
+        pos: Position,
+    },
+    Banner(&'static str),
+    // the stack greys out from bottom to top
+    GameOver,
+}
+
+impl Effect {
+    fn duration(&self) -> u32 {
+        match self {
+            Effect::LineFlash { .. } => LINE_FLASH_FRAMES,
+            Effect::LockFlash { .. } => LOCK_FLASH_FRAMES,
+            Effect::Banner(_) => BANNER_FRAMES,
+            Effect::GameOver => FIELD_HEIGHT as u32 * GAMEOVER_ROW_FRAMES,
+        }
+    }
+}
+
+// Short non-blocking effects, driven by engine events and advanced by frame ticks
+pub struct Animation {
+    enabled: bool,
+    effects: Vec<(Effect, u32)>, // effect and frames elapsed
+}
+
+impl Animation {
+    pub fn new(enabled: bool) -> Animation {
+        Animation {
+            enabled,
+            effects: vec![],
+        }
+    }
+
+    pub fn push(&mut self, events: Vec<Event>) {
+        if !self.enabled {
+            return;
+        }
+        let cleared = events
+            .iter()
+            .any(|event| matches!(event, Event::LineClear { .. }));
+        for event in events {
+            let effect = match event {
+                // a lock which clears lines is shown by the line flash instead
+                Event::Lock { mino, pos, tspin } => {
+                    if tspin && !cleared {
+                        self.start(Effect::Banner("T-SPIN"));
+                    }
+                    if cleared {
+                        continue;
+                    }
+                    Effect::LockFlash { mino, pos }
+                }
+                Event::LineClear { field, rows, tspin } => {
+                    match (tspin, rows.len()) {
+                        (true, 1) => self.start(Effect::Banner("T-SPIN SINGLE")),
+                        (true, 2) => self.start(Effect::Banner("T-SPIN DOUBLE")),
+                        (true, _) => self.start(Effect::Banner("T-SPIN TRIPLE")),
+                        (false, 4) => self.start(Effect::Banner("TETRIS")),
+                        _ => (),
+                    }
+                    Effect::LineFlash { field, rows }
+                }
+                Event::Attack(_) => continue,
+                Event::PerfectClear => Effect::Banner("PERFECT CLEAR"),
+                Event::GameOver => Effect::GameOver,
+            };
+            self.start(effect);
+        }
+    }
+
+    fn start(&mut self, effect: Effect) {
+        // a new effect replaces the running one of the same kind
+        self.effects
+            .retain(|(e, _)| std::mem::discriminant(e) != std::mem::discriminant(&effect));
+        self.effects.push((effect, 0));
+    }
+
+    pub fn tick(&mut self) {
+        for (_, frame) in self.effects.iter_mut() {
+            *frame += 1;
+        }
+        self.effects
+            .retain(|(effect, frame)| *frame < effect.duration());
+    }
+
+    pub fn clear(&mut self) {
+        self.effects.clear();
+    }
+
+    pub fn is_active(&self) -> bool {
+        !self.effects.is_empty()
+    }
+
+    pub fn draw(&self, game: &Game) {
+        self.draw_at(game, 0, Hud::Full);
+    }
+
+    // draw the game with the running effects on top
+    #[allow(clippy::needless_range_loop)]
+    pub fn draw_at(&self, game: &Game, col: usize, hud: Hud) {
+        draw_at(game, col, hud);
+
+        for (effect, frame) in &self.effects {
+            match effect {
+                Effect::LineFlash { field, rows } => {
+                    let mut field = **field;
+                    if (frame / LINE_FLASH_BLINK).is_multiple_of(2) {
+                        for &y in rows {
+                            for block in field[y][2..FIELD_WIDTH - 2].iter_mut() {
+                                *block = block_kind::FLASH;
+                            }
+                        }
+                    }
+                    draw_field(&field, col);
+                }
+                Effect::LockFlash { mino, pos } => {
+                    for y in 0..4 {
+                        for x in 0..4 {
+                            if mino[y][x] != block_kind::NONE {
+                                draw_block(pos.x + x, pos.y + y, block_kind::FLASH, col);
+                            }
+                        }
+                    }
+                }
+                Effect::Banner(text) => {
+                    print!("\x1b[{BANNER_ROW};{}H\x1b[1m{text:<16}\x1b[0m", col + 28);
+                }
+                Effect::GameOver => {
+                    let mut field = game.field;
+                    let greyed = (frame / GAMEOVER_ROW_FRAMES) as usize + 1;
+                    for row in field.iter_mut().rev().skip(2).take(greyed) {
+                        for block in row[2..FIELD_WIDTH - 2].iter_mut() {
+                            if *block != block_kind::NONE {
+                                *block = block_kind::DEAD;
+                            }
+                        }
+                    }
+                    draw_field(&field, col);
+                }
+            }
+        }
+
+        if !self
+            .effects
+            .iter()
+            .any(|(effect, _)| matches!(effect, Effect::Banner(_)))
+        {
+            print!("\x1b[{BANNER_ROW};{}H{:16}", col + 28, "");
+        }
+        println!();
+    }
+}
@@ -11,10 +11,15 @@ pub mod block_kind {
     pub const J: super::BlockColor = 7;
     pub const L: super::BlockColor = 8;
     pub const T: super::BlockColor = 9;
+    pub const FLASH: super::BlockColor = 10;
+    pub const DEAD: super::BlockColor = 11;
+    pub const GARBAGE: super::BlockColor = 12;
+    pub const TARGET: super::BlockColor = 13;
+    pub const HINT: super::BlockColor = 14;
 }
 
 // block color
-pub const COLOR_TABLE: [&str; 10] = [
+pub const COLOR_TABLE: [&str; 15] = [
     "\x1b[48;2;000;000;000m  ", // None
     "\x1b[48;2;127;127;127m__", // Wall
     "\x1b[48;2;000;000;000m[]", // Ghost
@@ -25,4 +30,9 @@ pub const COLOR_TABLE: [&str; 10] = [
     "\x1b[48;2;000;000;255m__", // J
     "\x1b[48;2;255;127;000m__", // L
     "\x1b[48;2;255;000;255m__", // T
+    "\x1b[48;2;255;255;255m__", // Flash
+    "\x1b[48;2;063;063;063m__", // Dead
+    "\x1b[48;2;095;095;095m__", // Garbage
+    "\x1b[48;2;000;063;127m<>", // Target
+    "\x1b[48;2;095;000;095m[]", // Hint
 ];
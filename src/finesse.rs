@@ -0,0 +1,346 @@
+use crate::animation::Animation;
+use crate::block::block_kind;
+use crate::game::*;
+use crate::mino::MinoShape;
+use crate::play::{drill, key_input, Drill, Retry};
+use getch_rs::Key;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+// One move of the mino: a key pressed once, or a shift key held until the mino stops (DAS)
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Move {
+    Tap(Input),
+    Das(Input),
+}
+
+impl Move {
+    pub fn apply(self, game: &mut Game) {
+        match self {
+            Move::Tap(input) => {
+                handle_input(game, input).ok();
+            }
+            Move::Das(input) => {
+                while shifts(game, input) {
+                    handle_input(game, input).ok();
+                }
+            }
+        }
+    }
+}
+
+// Moves before the mino is dropped. Soft drop is left out, so are the places only reachable with
+// it.
+const MOVES: [Move; 6] = [
+    Move::Tap(Input::Left),
+    Move::Tap(Input::Right),
+    Move::Das(Input::Left),
+    Move::Das(Input::Right),
+    Move::Tap(Input::RotateRight),
+    Move::Tap(Input::RotateLeft),
+];
+
+// whether the shift moves the mino, rather than pushing it against the wall or a block
+fn shifts(game: &Game, input: Input) -> bool {
+    let mut next = game.clone();
+    handle_input(&mut next, input).ok();
+    next.pos != game.pos
+}
+
+pub type Placements = HashMap<Vec<(usize, usize)>, Vec<Move>>;
+
+// The fewest moves bringing the current mino to each place it can be hard dropped to, keyed by
+// the cells it lands on so that rotations ending on the same cells are one place.
+pub fn placements(game: &Game) -> Placements {
+    search(game, &MOVES)
+}
+
+// places reachable with `moves`, see `placements`
+pub fn search(game: &Game, moves: &[Move]) -> Placements {
+    let mut start = game.clone();
+    start.events.clear();
+    let mut seen = HashSet::from([(start.mino, start.pos)]);
+    let mut queue = VecDeque::from([(start, vec![])]);
+    let mut placements = HashMap::new();
+    while let Some((game, inputs)) = queue.pop_front() {
+        let landed = ghost_pos(&game.field, &game.pos, &game.mino);
+        placements
+            .entry(mino_cells(&game.mino, &landed))
+            .or_insert_with(|| inputs.clone());
+        for &step in moves {
+            let mut next = game.clone();
+            step.apply(&mut next);
+            if seen.insert((next.mino, next.pos)) {
+                let mut inputs = inputs.clone();
+                inputs.push(step);
+                queue.push_back((next, inputs));
+            }
+        }
+    }
+    placements
+}
+
+// the keys of the moves, as in the help of `normal`, held ones marked with DAS
+pub fn format_moves(moves: &[Move]) -> String {
+    let keys: Vec<_> = moves
+        .iter()
+        .map(|step| {
+            let (Move::Tap(input) | Move::Das(input)) = step;
+            let key = match input {
+                Input::Left => "<-",
+                Input::Right => "->",
+                Input::RotateRight => "up",
+                Input::RotateLeft => "z",
+                Input::SoftDrop => "down",
+                Input::HardDrop => "space",
+                Input::Hold => "c",
+            };
+            match step {
+                Move::Tap(_) => key.to_string(),
+                Move::Das(_) => format!("DAS{key}"),
+            }
+        })
+        .collect();
+    if keys.is_empty() {
+        "nothing".into()
+    } else {
+        keys.join(" ")
+    }
+}
+
+// Moves made by the player with a mino. The terminal has no key releases, so presses of the same
+// shift key in a row which bring the mino to the wall or a block are one move, the key held.
+#[derive(Default)]
+pub struct Moves {
+    count: usize,
+    shift: Option<Input>, // the shift key of the presses in a row
+    before: usize,        // count before them
+    presses: usize,
+}
+
+impl Moves {
+    // after the input was handled by `game`
+    pub fn input(&mut self, input: Input, game: &Game) {
+        match input {
+            Input::Left | Input::Right => {
+                if self.shift != Some(input) {
+                    self.shift = Some(input);
+                    self.before = self.count;
+                    self.presses = 0;
+                }
+                self.presses += 1;
+                let held = if shifts(game, input) { self.presses } else { 1 };
+                self.count = self.before + held;
+            }
+            Input::RotateRight | Input::RotateLeft => {
+                self.count += 1;
+                self.shift = None;
+            }
+            _ => self.shift = None,
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+}
+
+// Finesse of the minos placed by the player
+#[derive(Default)]
+pub struct Finesse {
+    start: Option<Game>, // the game when the current mino appeared
+    moves: Moves,        // moves made with the current mino
+    pub faults: usize,   // moves more than needed, over the game
+}
+
+impl Finesse {
+    // a new mino to follow, after a lock, a hold or a restart
+    pub fn start(&mut self, game: &Game) {
+        self.start = Some(game.clone());
+        self.moves = Moves::default();
+    }
+
+    // after the input was handled by `game`
+    pub fn input(&mut self, input: Input, game: &Game) {
+        self.moves.input(input, game);
+    }
+
+    // Count the faults of the mino which just locked. Places reached with soft drop are not judged.
+    pub fn lock(&mut self, mino: &MinoShape, pos: &Position) {
+        let Some(start) = self.start.take() else {
+            return;
+        };
+        if let Some(best) = placements(&start).get(&mino_cells(mino, pos)) {
+            self.faults += self.moves.count().saturating_sub(best.len());
+        }
+    }
+}
+
+// The trainer shows where to place each mino on an empty field, and takes the mino back until
+// it is placed there with the fewest moves
+struct Trainer {
+    retry: Retry,
+    target: Vec<(usize, usize)>,
+    best: Vec<Move>,
+    moves: Moves,
+    anim: Animation,
+    rng: ChaCha8Rng, // targets
+    placed: usize,
+    correct: usize,
+    streak: usize,
+    message: String,
+}
+
+impl Trainer {
+    fn new(options: Options, animation: bool) -> Trainer {
+        let mut trainer = Trainer {
+            retry: Retry::new(Game::with_options(options)),
+            target: vec![],
+            best: vec![],
+            moves: Moves::default(),
+            anim: Animation::new(animation),
+            rng: ChaCha8Rng::seed_from_u64(options.seed.unwrap_or_else(rand::random)),
+            placed: 0,
+            correct: 0,
+            streak: 0,
+            message: String::new(),
+        };
+        trainer.next_target();
+        trainer
+    }
+
+    // a new target for the current mino, on an empty field
+    fn next_target(&mut self) {
+        self.retry.game.field = EMPTY_FIELD;
+        self.retry.keep();
+        let mut placements: Vec<_> = placements(&self.retry.game).into_iter().collect();
+        placements.sort_by(|a, b| a.0.cmp(&b.0));
+        let (target, best) = placements.swap_remove(self.rng.gen_range(0..placements.len()));
+        self.target = target;
+        self.best = best;
+        self.moves = Moves::default();
+    }
+
+    // the field is empty, so the game never ends
+    fn update(&mut self) {
+        let events = std::mem::take(&mut self.retry.game.events);
+        let locked = events.iter().find_map(|event| match event {
+            Event::Lock { mino, pos, .. } => Some(mino_cells(mino, pos)),
+            _ => None,
+        });
+        self.anim.push(events);
+        let Some(cells) = locked else {
+            return;
+        };
+        self.placed += 1;
+        let best = format_moves(&self.best);
+        if cells == self.target && self.moves.count() <= self.best.len() {
+            self.correct += 1;
+            self.streak += 1;
+            self.message = "OK".into();
+        } else {
+            self.streak = 0;
+            self.message = if cells != self.target {
+                format!("WRONG PLACE, try: {best}")
+            } else {
+                format!(
+                    "{} MOVES, {} NEEDED: {best}",
+                    self.moves.count(),
+                    self.best.len()
+                )
+            };
+            // the same mino again
+            self.retry.retry();
+            self.moves = Moves::default();
+            return;
+        }
+        self.next_target();
+    }
+}
+
+impl Drill for Trainer {
+    fn tick(&mut self) -> bool {
+        let redraw = self.anim.is_active();
+        self.anim.tick();
+        redraw
+    }
+
+    fn key(&mut self, key: &Key) -> bool {
+        let Some(input) = key_input(key) else {
+            return false;
+        };
+        handle_input(&mut self.retry.game, input).ok();
+        self.moves.input(input, &self.retry.game);
+        self.update();
+        true
+    }
+
+    fn draw(&self) {
+        self.anim.draw(&self.retry.game);
+        draw_overlay(&self.retry.game, &self.target, block_kind::TARGET, 0);
+        print!(
+            "\x1b[24;1HFINESSE  correct {}/{}  streak {}  {}\x1b[K",
+            self.correct, self.placed, self.streak, self.message
+        );
+        print!("\x1b[25;1Hplace the mino on <> with the fewest moves  q: quit\x1b[K");
+        println!();
+    }
+
+    fn result(&self) -> String {
+        format!("finesse: {}/{} correct", self.correct, self.placed)
+    }
+}
+
+pub fn trainer(options: Options, animation: bool) -> ! {
+    let options = Options {
+        gravity: false,
+        hold: false,
+        ..options
+    };
+    drill(Trainer::new(options, animation))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mino::{MinoKind, MINOS};
+
+    #[test]
+    fn test_finesse() {
+        let setup = Setup {
+            minos: vec![MinoKind::O, MinoKind::T],
+            ..Default::default()
+        };
+        let game = Game::with_setup(Options::default(), &setup).unwrap();
+        let placements = placements(&game);
+        // the O can go to 9 columns, whatever its rotation
+        assert_eq!(placements.len(), 9);
+        let spawn = mino_cells(&game.mino, &ghost_pos(&game.field, &game.pos, &game.mino));
+        assert_eq!(placements[&spawn], vec![]);
+        // to the wall in one move
+        let mut left = game.clone();
+        Move::Das(Input::Left).apply(&mut left);
+        let wall = mino_cells(&left.mino, &ghost_pos(&left.field, &left.pos, &left.mino));
+        assert_eq!(placements[&wall], vec![Move::Das(Input::Left)]);
+
+        let mut finesse = Finesse::default();
+        let play = |finesse: &mut Finesse, inputs: &[Input]| {
+            let mut game = game.clone();
+            finesse.start(&game);
+            for &input in inputs {
+                handle_input(&mut game, input).unwrap();
+                finesse.input(input, &game);
+            }
+            hard_drop(&mut game);
+            finesse.lock(&MINOS[MinoKind::O as usize], &game.pos);
+        };
+        play(&mut finesse, &[Input::Left, Input::Right, Input::Right]);
+        assert_eq!(finesse.faults, 2);
+        // the key held to the wall, and one more repeat against it
+        play(&mut finesse, &[Input::Left; 6]);
+        assert_eq!(finesse.faults, 2);
+        play(&mut finesse, &[Input::Left, Input::Left, Input::Right]);
+        assert_eq!(finesse.faults, 4);
+    }
+}
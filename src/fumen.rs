@@ -0,0 +1,283 @@
+// Fumen, the field notation of the fumen.zui.jp editor, version 115.
+// Only the field and the quiz comment `#Q=[hold](current)next` of the first page are read and
+// written, the mino of the page and the other pages are left out.
+use crate::block::{block_kind, BlockColor};
+use crate::game::*;
+use crate::notation::{format_minos, parse_minos, FIELD_COLUMNS, FIELD_ROWS};
+
+const PREFIXES: [&str; 3] = ["v115@", "m115@", "d115@"];
+const TABLE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const COMMENT_TABLE: &[u8; 95] =
+    b" !\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~";
+const COMMENT_CHARS: usize = COMMENT_TABLE.len() + 1;
+
+const FUMEN_TOP: usize = 23; // rows of a fumen field, a garbage row is below them
+const FUMEN_BLOCKS: usize = (FUMEN_TOP + 1) * FIELD_COLUMNS;
+
+// block of each fumen color: empty, I, L, O, Z, T, J, S, gray
+const BLOCKS: [BlockColor; 9] = [
+    block_kind::NONE,
+    block_kind::I,
+    block_kind::L,
+    block_kind::O,
+    block_kind::Z,
+    block_kind::T,
+    block_kind::J,
+    block_kind::S,
+    block_kind::GARBAGE,
+];
+
+// flags of the page, after the mino and its rotation and position
+const COLORIZE: usize = 4;
+const COMMENT: usize = 8;
+
+pub fn is_fumen(text: &str) -> bool {
+    PREFIXES.iter().any(|prefix| text.contains(prefix))
+}
+
+// Field row of the block at `index` in a fumen field, None above the field and for the garbage row
+fn field_row(index: usize) -> Option<usize> {
+    let y = index / FIELD_COLUMNS; // from the top of the fumen field
+    (FUMEN_TOP - FIELD_ROWS..FUMEN_TOP)
+        .contains(&y)
+        .then(|| y + 1 + FIELD_ROWS - FUMEN_TOP)
+}
+
+fn block_color(block: BlockColor) -> usize {
+    match block {
+        block_kind::I..=block_kind::T => BLOCKS.iter().position(|&b| b == block).unwrap(),
+        block_kind::NONE => 0,
+        _ => 8,
+    }
+}
+
+struct Values<'a> {
+    data: &'a [u8],
+}
+
+impl Values<'_> {
+    // `digits` base 64 digits, the lowest first
+    fn poll(&mut self, digits: usize) -> Result<usize, String> {
+        if self.data.len() < digits {
+            return Err("fumen ends too early".into());
+        }
+        let (head, tail) = self.data.split_at(digits);
+        self.data = tail;
+        head.iter().rev().try_fold(0, |value, &c| {
+            let digit = TABLE
+                .iter()
+                .position(|&t| t == c)
+                .ok_or_else(|| format!("unexpected {:?} in fumen", c as char))?;
+            Ok(value * TABLE.len() + digit)
+        })
+    }
+}
+
+fn push(data: &mut String, mut value: usize, digits: usize) {
+    for _ in 0..digits {
+        data.push(TABLE[value % TABLE.len()] as char);
+        value /= TABLE.len();
+    }
+}
+
+pub fn decode(text: &str) -> Result<Setup, String> {
+    let start = PREFIXES
+        .iter()
+        .filter_map(|prefix| text.find(prefix).map(|i| i + prefix.len()))
+        .min()
+        .ok_or("not a v115 fumen")?;
+    let data: Vec<u8> = text[start..]
+        .bytes()
+        .filter(|&c| c != b'?' && !c.is_ascii_whitespace())
+        .collect();
+    let mut values = Values { data: &data };
+
+    let mut setup = Setup::default();
+    let mut index = 0;
+    let mut unchanged = false;
+    while index < FUMEN_BLOCKS {
+        let run = values.poll(2)?;
+        let (diff, count) = (run / FUMEN_BLOCKS, run % FUMEN_BLOCKS + 1);
+        unchanged = diff == 8 && count == FUMEN_BLOCKS;
+        // the first page is a difference from the empty field
+        let color = diff.checked_sub(8).filter(|&c| c < BLOCKS.len());
+        let Some(color) = color else {
+            return Err("broken fumen field".into());
+        };
+        for i in index..(index + count).min(FUMEN_BLOCKS) {
+            match field_row(i) {
+                Some(y) => setup.field[y][2 + i % FIELD_COLUMNS] = BLOCKS[color],
+                None if color != 0 => return Err("blocks above the field or rising".into()),
+                None => {}
+            }
+        }
+        index += count;
+    }
+    if unchanged {
+        values.poll(1)?; // pages with the same field
+    }
+
+    let action = values.poll(3)?;
+    if (action / (8 * 4 * FUMEN_BLOCKS)) & COMMENT != 0 {
+        let length = values.poll(2)?;
+        let mut escaped = String::new();
+        for _ in 0..length.div_ceil(4) {
+            let mut value = values.poll(5)?;
+            for _ in 0..4 {
+                escaped.push(*COMMENT_TABLE.get(value % COMMENT_CHARS).unwrap_or(&b' ') as char);
+                value /= COMMENT_CHARS;
+            }
+        }
+        escaped.truncate(length);
+        if let Some(quiz) = unescape(&escaped).strip_prefix("#Q=") {
+            read_quiz(quiz, &mut setup)?;
+        }
+    }
+    Ok(setup)
+}
+
+// `[hold](current)next`
+fn read_quiz(quiz: &str, setup: &mut Setup) -> Result<(), String> {
+    let broken = || format!("broken quiz {quiz:?}");
+    let hold = quiz.strip_prefix('[').ok_or_else(broken)?;
+    let (hold, current) = hold.split_once("](").ok_or_else(broken)?;
+    let (current, next) = current.split_once(')').ok_or_else(broken)?;
+    setup.hold = parse_minos(hold)?.first().copied();
+    setup.minos = parse_minos(current)?;
+    setup.minos.extend(parse_minos(next)?);
+    Ok(())
+}
+
+pub fn encode(setup: &Setup) -> String {
+    let mut data = String::new();
+
+    let mut colors = [0; FUMEN_BLOCKS];
+    for (i, color) in colors.iter_mut().enumerate() {
+        if let Some(y) = field_row(i) {
+            *color = block_color(setup.field[y][2 + i % FIELD_COLUMNS]);
+        }
+    }
+    // runs of the same difference from the empty field
+    let mut start = 0;
+    for i in 1..=FUMEN_BLOCKS {
+        if i == FUMEN_BLOCKS || colors[i] != colors[start] {
+            push(
+                &mut data,
+                (colors[start] + 8) * FUMEN_BLOCKS + i - start - 1,
+                2,
+            );
+            start = i;
+        }
+    }
+    if colors.iter().all(|&color| color == 0) {
+        push(&mut data, 0, 1);
+    }
+
+    let quiz = if setup.minos.is_empty() && setup.hold.is_none() {
+        None
+    } else {
+        let (current, next) = setup.minos.split_at(setup.minos.len().min(1));
+        Some(escape(&format!(
+            "#Q=[{}]({}){}",
+            format_minos(setup.hold.as_slice()),
+            format_minos(current),
+            format_minos(next)
+        )))
+    };
+    let flags = COLORIZE | if quiz.is_some() { COMMENT } else { 0 };
+    push(&mut data, flags * 8 * 4 * FUMEN_BLOCKS, 3);
+    if let Some(quiz) = quiz {
+        push(&mut data, quiz.len(), 2);
+        for chunk in quiz.as_bytes().chunks(4) {
+            let value = chunk.iter().rev().fold(0, |value, &c| {
+                value * COMMENT_CHARS + COMMENT_TABLE.iter().position(|&t| t == c).unwrap()
+            });
+            push(&mut data, value, 5);
+        }
+    }
+
+    // the editor breaks long fumens with `?`
+    if data.len() >= 41 {
+        let (head, tail) = data.split_at(42.min(data.len()));
+        let mut chunks = vec![head];
+        chunks.extend(
+            tail.as_bytes()
+                .chunks(47)
+                .map(|c| std::str::from_utf8(c).unwrap()),
+        );
+        data = chunks.join("?");
+    }
+    format!("{}{data}", PREFIXES[0])
+}
+
+// JavaScript's `escape`, which the comments go through
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            'A'..='Z' | 'a'..='z' | '0'..='9' | '@' | '*' | '_' | '+' | '-' | '.' | '/' => {
+                escaped.push(c)
+            }
+            c if (c as u32) < 0x100 => escaped += &format!("%{:02X}", c as u32),
+            c => {
+                for unit in c.encode_utf16(&mut [0; 2]) {
+                    escaped += &format!("%u{unit:04X}");
+                }
+            }
+        }
+    }
+    escaped
+}
+
+fn unescape(text: &str) -> String {
+    let mut units = vec![];
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let hex = |digits: &str| u16::from_str_radix(digits, 16).ok();
+        let unit = match c {
+            '%' if rest.starts_with("%u") => rest.get(2..6).and_then(hex).map(|u| (u, 6)),
+            '%' => rest.get(1..3).and_then(hex).map(|u| (u, 3)),
+            _ => None,
+        };
+        match unit {
+            Some((unit, len)) => {
+                units.push(unit);
+                rest = &rest[len..];
+            }
+            None => {
+                units.extend(c.encode_utf16(&mut [0; 2]).iter());
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    String::from_utf16_lossy(&units)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mino::MinoKind;
+    use crate::notation::parse_field;
+
+    #[test]
+    fn test_fumen() {
+        let empty = Setup::default();
+        assert_eq!(encode(&empty), "v115@vhAAgH");
+        assert!(decode("v115@vhAAgH").unwrap().field == EMPTY_FIELD);
+
+        let setup = Setup {
+            field: parse_field("XXXX.XXXXX\nIIIIOO.TSZ\nJJJLLLSSZZ\n").unwrap(),
+            minos: vec![MinoKind::T, MinoKind::I, MinoKind::O],
+            hold: Some(MinoKind::L),
+        };
+        let fumen = encode(&setup);
+        assert!(fumen.contains('?'));
+        let decoded = decode(&format!("https://fumen.zui.jp/?{fumen}")).unwrap();
+        assert!(decoded.field == setup.field);
+        assert_eq!(decoded.minos, setup.minos);
+        assert_eq!(decoded.hold, setup.hold);
+
+        assert_eq!(unescape(&escape("#Q=[](T)é")), "#Q=[](T)é");
+        assert!(decode("v115@vh").is_err());
+    }
+}
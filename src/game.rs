@@ -1,8 +1,16 @@
 use crate::block::{block_kind, block_kind::WALL as W, BlockColor, COLOR_TABLE};
-use crate::mino::{gen_mino_7, MinoKind, MinoShape, MINOS};
+use crate::mino::{block_of, gen_mino_7, turn_right, MinoKind, MinoShape, MINOS};
+use crate::stats::{format_time, Stats, PIECE_NAMES};
+use crate::terminal;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
 
 pub const NEXT_LENGTH: usize = 3;
+pub const NEXT_LENGTH_MAX: usize = 7;
+
+pub const FPS: u64 = 60;
 
 pub const FIELD_WIDTH: usize = 12 + 2;
 pub const FIELD_HEIGHT: usize = 22 + 1;
@@ -15,9 +23,43 @@ pub const SCORE_TABLE: [usize; 5] = [
     100, //  4lines
 ];
 
+// attack bonus by the number of consecutive clears before this one
+pub const COMBO_TABLE: [usize; 12] = [0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 4, 5];
+pub const PERFECT_CLEAR_ATTACK: usize = 10;
+// score of a perfect clear, on top of the score of the lines
+pub const PERFECT_CLEAR_SCORE: usize = 200;
+// garbage lines which can rise at once
+pub const GARBAGE_CAP: usize = 8;
+
 pub type FieldSize = [[BlockColor; FIELD_WIDTH]; FIELD_HEIGHT];
 
-#[derive(Clone, Copy)]
+pub const EMPTY_FIELD: FieldSize = [
+    [0, W, W, W, 0, 0, 0, 0, 0, 0, W, W, W, 0],
+    [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
+    [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
+    [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
+    [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
+    [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
+    [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
+    [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
+    [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
+    [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
+    [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
+    [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
+    [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
+    [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
+    [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
+    [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
+    [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
+    [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
+    [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
+    [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
+    [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
+    [0, W, W, W, W, W, W, W, W, W, W, W, W, 0],
+    [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
 pub struct Position {
     pub x: usize,
     pub y: usize,
@@ -29,8 +71,86 @@ impl Position {
     }
 }
 
+// What finishes a game other than topping out
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Goal {
+    Endless,
+    Lines(usize),   // clear this many lines
+    Time(u64),      // score as much as possible in this many frames
+    Level(usize),   // finish this level
+    Garbage(usize), // clear this many garbage lines
+}
+
+// Rules which can be changed for each game
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct Options {
+    pub next_length: usize, // visible next minos, up to `NEXT_LENGTH_MAX`
+    pub hold: bool,
+    pub goal: Goal,
+    pub start_level: usize,
+    pub seed: Option<u64>, // random when not given
+    pub dig_rows: usize,   // garbage lines kept on the field until the goal is dealt
+    pub messiness: u8,     // chance in % that the hole moves on the next garbage line
+    pub gravity: bool,
+    pub infinite_hold: bool, // hold more than once for the same mino
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            next_length: NEXT_LENGTH,
+            hold: true,
+            goal: Goal::Endless,
+            start_level: 1,
+            seed: None,
+            dig_rows: 0,
+            messiness: 100,
+            gravity: true,
+            infinite_hold: false,
+        }
+    }
+}
+
+// Things that happened in the engine, drained by the frontend
 #[derive(Clone)]
+pub enum Event {
+    Lock {
+        mino: MinoShape,
+        pos: Position,
+        tspin: bool,
+    },
+    // `field` is the field right before the `rows` collapse
+    LineClear {
+        field: Box<FieldSize>,
+        rows: Vec<usize>,
+        tspin: bool,
+    },
+    // garbage lines sent to the opponent, after cancelling the incoming ones
+    Attack(usize),
+    PerfectClear,
+    GameOver,
+}
 
+// Player operations
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Input {
+    Left,
+    Right,
+    SoftDrop,
+    HardDrop,
+    RotateRight,
+    RotateLeft,
+    Hold,
+}
+
+// Incoming garbage lines sharing a hole
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct Garbage {
+    pub lines: usize,
+    pub hole: usize,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Game {
     pub field: FieldSize,
     pub pos: Position,
@@ -41,48 +161,95 @@ pub struct Game {
     pub next_buf: VecDeque<MinoShape>,
     pub score: usize,
     pub total_line: usize, // total line deletion
+    pub rotated: bool,     // last successful move was a rotation
+    pub frame: u64,        // frames since the game started
+    pub fall_frame: u64,   // frames since the mino last fell
+    #[serde(skip)]
+    pub events: Vec<Event>,
+    pub stats: Stats,
+    pub options: Options,
+    pub seed: u64,
+    pub rng: ChaCha8Rng,         // minos
+    pub garbage_rng: ChaCha8Rng, // garbage holes, apart so that garbage doesn't change the minos
+    pub garbage_hole: usize,     // column of the hole in the last garbage line
+    pub garbage_dealt: usize,    // garbage lines added for digging
+    pub incoming: VecDeque<Garbage>,
+    pub combo: usize, // consecutive line clears
+    pub b2b: bool,    // last clear was a tetris or a T-spin
+}
+
+// A position to start a game from, see `notation` and `fumen`
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Setup {
+    pub field: FieldSize,
+    pub minos: Vec<MinoKind>, // the current mino, then the next ones
+    pub hold: Option<MinoKind>,
+}
+
+impl Default for Setup {
+    fn default() -> Setup {
+        Setup {
+            field: EMPTY_FIELD,
+            minos: vec![],
+            hold: None,
+        }
+    }
 }
 
 impl Game {
-    pub fn new() -> Game {
+    pub fn with_options(options: Options) -> Game {
+        let seed = options.seed.unwrap_or_else(rand::random);
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        let mut garbage_rng = ChaCha8Rng::seed_from_u64(seed);
+        garbage_rng.set_stream(1);
         let mut game = Game {
-            field: [
-                [0, W, W, W, 0, 0, 0, 0, 0, 0, W, W, W, 0],
-                [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
-                [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
-                [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
-                [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
-                [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
-                [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
-                [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
-                [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
-                [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
-                [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
-                [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
-                [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
-                [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
-                [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
-                [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
-                [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
-                [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
-                [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
-                [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
-                [0, W, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, W, 0],
-                [0, W, W, W, W, W, W, W, W, W, W, W, W, 0],
-                [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-            ],
+            field: EMPTY_FIELD,
             pos: Position::init(),
-            mino: MINOS[rand::random::<MinoKind>() as usize],
+            mino: MINOS[rng.gen::<MinoKind>() as usize],
             hold: None,
             holded: false,
-            next: gen_mino_7().into(),
-            next_buf: gen_mino_7().into(),
+            next: gen_mino_7(&mut rng).into(),
+            next_buf: gen_mino_7(&mut rng).into(),
             score: 0,
             total_line: 0,
+            rotated: false,
+            frame: 0,
+            fall_frame: 0,
+            events: vec![],
+            stats: Stats::default(),
+            options,
+            seed,
+            garbage_hole: garbage_rng.gen_range(0..FIELD_WIDTH - 4),
+            rng,
+            garbage_rng,
+            garbage_dealt: 0,
+            incoming: VecDeque::new(),
+            combo: 0,
+            b2b: false,
         };
+        dig(&mut game).ok();
         spawn_mino(&mut game).ok();
         game
     }
+
+    // A game from the setup, the random minos come after its minos.
+    // Err when the current mino doesn't fit in the field.
+    pub fn with_setup(options: Options, setup: &Setup) -> Result<Game, ()> {
+        let mut game = Game::with_options(options);
+        game.field = setup.field;
+        game.hold = setup.hold.map(|kind| MINOS[kind as usize]);
+        let mut next: VecDeque<_> = setup
+            .minos
+            .iter()
+            .map(|&kind| MINOS[kind as usize])
+            .collect();
+        next.push_back(game.mino);
+        next.append(&mut game.next);
+        game.next = next;
+        game.stats = Stats::default();
+        spawn_mino(&mut game)?;
+        Ok(game)
+    }
 }
 
 pub fn is_collision(field: &FieldSize, pos: &Position, mino: &MinoShape) -> bool {
@@ -99,9 +266,20 @@ pub fn is_collision(field: &FieldSize, pos: &Position, mino: &MinoShape) -> bool
     false
 }
 
-#[allow(clippy::needless_range_loop)]
-pub fn draw(
-    Game {
+// How much is drawn around the field
+#[derive(Clone, Copy, PartialEq)]
+pub enum Hud {
+    Full,
+    Compact, // no statistics panel, for side by side games
+}
+
+pub fn draw(game: &Game) {
+    draw_at(game, 0, Hud::Full);
+}
+
+// draw the game with its left edge at the column `col`
+pub fn draw_at(game: &Game, col: usize, hud: Hud) {
+    let Game {
         field,
         pos,
         mino,
@@ -111,8 +289,22 @@ pub fn draw(
         next_buf: _,
         score,
         total_line,
-    }: &Game,
-) {
+        rotated: _,
+        frame,
+        fall_frame: _,
+        events: _,
+        stats,
+        options,
+        seed: _,
+        rng: _,
+        garbage_rng: _,
+        garbage_hole: _,
+        garbage_dealt: _,
+        incoming: _,
+        combo,
+        b2b,
+    } = game;
+
     let mut field_buf = *field;
 
     let ghost_pos = ghost_pos(field, pos, mino);
@@ -133,21 +325,27 @@ pub fn draw(
     }
 
     // hold rendering
-    println!("\x1b[2;28HHOLD");
-    if let Some(hold) = hold {
+    if options.hold {
+        println!("\x1b[2;{}HHOLD", col + 28);
+        let hold = hold.unwrap_or_default();
         for y in 0..4 {
-            print!("\x1b[{};28H", y + 3);
+            print!("\x1b[{};{}H", y + 3, col + 28);
             for x in 0..4 {
                 print!("{}", COLOR_TABLE[hold[y][x]]);
             }
         }
+        print!("\x1b[0m");
     }
 
     // next minos rendering
-    println!("\x1b[8;28HNEXT");
-    for (i, next) in next.iter().take(NEXT_LENGTH).enumerate() {
-        for y in 0..4 {
-            print!("\x1b[{};28H", i * 4 + y + 9);
+    // only the middle 2 rows are drawn, the spawn shapes don't use the others
+    if options.next_length > 0 {
+        println!("\x1b[8;{}HNEXT", col + 28);
+    }
+    for (i, next) in next.iter().take(options.next_length).enumerate() {
+        let (row, col) = (i % 4 * 3 + 9, col + i / 4 * 10 + 28);
+        for y in 1..3 {
+            print!("\x1b[{};{col}H", row + y - 1);
             for x in 0..4 {
                 print!("{}", COLOR_TABLE[next[y][x]]);
             }
@@ -155,25 +353,127 @@ pub fn draw(
         }
     }
 
-    // score rendering
-    println!("\x1b[22;28H{score}");
+    // incoming garbage meter, right of the field
+    let incoming = incoming_lines(game);
+    for y in 1..FIELD_HEIGHT - 2 {
+        let color = if FIELD_HEIGHT - 2 - y <= incoming {
+            "\x1b[41m"
+        } else {
+            "\x1b[0m"
+        };
+        print!("\x1b[{};{}H{color} \x1b[0m", y + 2, col + 25);
+    }
 
-    // totle line rendering
-    println!("\x1b[24;28H{total_line} lines in total");
+    draw_field(&field_buf, col);
 
-    // field rendering
-    println!("\x1b[H");
-    for y in 0..FIELD_HEIGHT - 1 {
-        for x in 1..FIELD_WIDTH - 1 {
-            print!("{}", COLOR_TABLE[field_buf[y][x]]);
+    if hud == Hud::Compact {
+        let rows = [
+            format!("SCORE  {score}"),
+            format!("LINES  {total_line}"),
+            format!("SENT   {}", stats.attack),
+        ];
+        for (i, row) in rows.iter().enumerate() {
+            print!("\x1b[{};{}H{row:<16}", i + 22, col + 28);
         }
         println!();
+        return;
+    }
+
+    // statistics rendering
+    let mut rows = vec![
+        format!("SCORE  {score}"),
+        format!("TIME   {}", format_time(*frame)),
+        format!("LEVEL  {}", level(game)),
+        format!("LINES  {total_line}"),
+        format!("PIECES {}", stats.pieces),
+        format!("PPS    {:.2}", stats.pps(*frame)),
+        format!("LPM    {:.1}", stats.lpm(*total_line, *frame)),
+        format!("APM    {:.1}", stats.apm(*frame)),
+        format!("TETRIS {:.0}%", stats.tetris_rate(*total_line)),
+    ];
+    match options.goal {
+        Goal::Endless => (),
+        Goal::Lines(lines) => rows.push(format!("LEFT   {}", lines.saturating_sub(*total_line))),
+        Goal::Time(time) => rows.push(format!(
+            "LEFT   {}",
+            format_time(time.saturating_sub(*frame))
+        )),
+        Goal::Level(_) => rows.push(format!("LEFT   {}", lines_to_goal(game))),
+        Goal::Garbage(lines) => rows.push(format!(
+            "LEFT   {}",
+            lines.saturating_sub(stats.garbage_cleared)
+        )),
+    }
+    if *combo > 1 {
+        rows.push(format!("COMBO  {}", combo - 1));
+    }
+    if *b2b {
+        rows.push("B2B".to_string());
+    }
+    if stats.perfect_clears > 0 {
+        rows.push(format!("PC     {}", stats.perfect_clears));
+    }
+    for (i, row) in rows.iter().enumerate() {
+        print!("\x1b[{};{}H{row}\x1b[K", i + 2, col + 50);
+    }
+    // the rows which were left when the rows got fewer
+    for i in rows.len()..13 {
+        print!("\x1b[{};{}H\x1b[K", i + 2, col + 50);
+    }
+    for (i, (name, count)) in PIECE_NAMES.iter().zip(stats.received).enumerate() {
+        let bar = COLOR_TABLE[block_kind::I + i].repeat(count.min(20) / 2);
+        print!(
+            "\x1b[{};{}H{name} {count:>3} {bar}\x1b[0m\x1b[K",
+            i + 16,
+            col + 50
+        );
+    }
+    println!();
+}
+
+// the field starts from the second row of the screen
+pub fn draw_field(field: &FieldSize, col: usize) {
+    for (y, row) in field.iter().take(FIELD_HEIGHT - 1).enumerate() {
+        print!("\x1b[{};{}H", y + 2, col + 1);
+        for block in &row[1..FIELD_WIDTH - 1] {
+            print!("{}", COLOR_TABLE[*block]);
+        }
     }
 
     // reset color info
     println!("\x1b[0m");
 }
 
+// draw a single block of the field over what is already on the screen
+pub fn draw_block(x: usize, y: usize, block: BlockColor, col: usize) {
+    print!(
+        "\x1b[{};{}H{}\x1b[0m",
+        y + 2,
+        col + (x - 1) * 2 + 1,
+        COLOR_TABLE[block]
+    );
+}
+
+// field cells of the mino at `pos`, row by row
+pub fn mino_cells(mino: &MinoShape, pos: &Position) -> Vec<(usize, usize)> {
+    (0..4)
+        .flat_map(|y| (0..4).map(move |x| (x, y)))
+        .filter(|&(x, y)| mino[y][x] != block_kind::NONE)
+        .map(|(x, y)| (pos.x + x, pos.y + y))
+        .collect()
+}
+
+// draw the cells where nothing is on the field or the current mino, like a ghost
+pub fn draw_overlay(game: &Game, cells: &[(usize, usize)], block: BlockColor, col: usize) {
+    let current = mino_cells(&game.mino, &game.pos);
+    for &(x, y) in cells {
+        if game.field[y][x] == block_kind::NONE && !current.contains(&(x, y)) {
+            draw_block(x, y, block, col);
+        }
+    }
+    println!();
+}
+
 pub fn fix_mino(
     Game {
         field,
@@ -185,6 +485,20 @@ pub fn fix_mino(
         next_buf: _,
         score: _,
         total_line: _,
+        rotated: _,
+        frame: _,
+        fall_frame: _,
+        events: _,
+        stats: _,
+        options: _,
+        seed: _,
+        rng: _,
+        garbage_rng: _,
+        garbage_hole: _,
+        garbage_dealt: _,
+        incoming: _,
+        combo: _,
+        b2b: _,
     }: &mut Game,
 ) {
     for y in 0..4 {
@@ -196,17 +510,16 @@ pub fn fix_mino(
     }
 }
 
+fn is_line_full(line: &[BlockColor; FIELD_WIDTH]) -> bool {
+    line[1..FIELD_WIDTH - 1]
+        .iter()
+        .all(|&block| block != block_kind::NONE)
+}
+
 pub fn erase_line(field: &mut FieldSize) -> usize {
     let mut line_count = 0;
     for y in 1..FIELD_HEIGHT - 2 {
-        let mut can_erase = true;
-        for x in 1..FIELD_WIDTH - 1 {
-            if field[y][x] == 0 {
-                can_erase = false;
-                break;
-            }
-        }
-        if can_erase {
+        if is_line_full(&field[y]) {
             line_count += 1;
             for y2 in (2..=y).rev() {
                 field[y2] = field[y2 - 1];
@@ -216,20 +529,121 @@ pub fn erase_line(field: &mut FieldSize) -> usize {
     line_count
 }
 
+pub fn handle_input(game: &mut Game, input: Input) -> Result<(), ()> {
+    match input {
+        Input::Left => {
+            let new_pos = Position {
+                x: game.pos.x.checked_sub(1).unwrap_or(game.pos.x),
+                y: game.pos.y,
+            };
+            move_mino(game, new_pos);
+        }
+        Input::Right => {
+            let new_pos = Position {
+                x: game.pos.x + 1,
+                y: game.pos.y,
+            };
+            move_mino(game, new_pos);
+        }
+        Input::SoftDrop => {
+            let new_pos = Position {
+                x: game.pos.x,
+                y: game.pos.y + 1,
+            };
+            move_mino(game, new_pos);
+        }
+        Input::HardDrop => {
+            hard_drop(game);
+            return landing(game);
+        }
+        Input::RotateRight => rotate_right(game),
+        Input::RotateLeft => rotate_left(game),
+        Input::Hold => hold(game),
+    }
+    Ok(())
+}
+
 pub fn move_mino(game: &mut Game, new_pos: Position) {
     if !is_collision(&game.field, &new_pos, &game.mino) {
-        game.pos = new_pos
+        game.pos = new_pos;
+        game.rotated = false;
+    }
+}
+
+pub fn goal_reached(game: &Game) -> bool {
+    match game.options.goal {
+        Goal::Endless => false,
+        Goal::Lines(lines) => game.total_line >= lines,
+        Goal::Time(time) => game.frame >= time,
+        Goal::Level(level) => level < self::level(game),
+        Goal::Garbage(lines) => game.stats.garbage_cleared >= lines,
+    }
+}
+
+// lines until the level goal is finished
+fn lines_to_goal(game: &Game) -> usize {
+    let Goal::Level(level) = game.options.goal else {
+        return 0;
+    };
+    (level + 1)
+        .saturating_sub(game.options.start_level)
+        .saturating_mul(10)
+        .saturating_sub(game.total_line)
+}
+
+// level goes up every 10 lines from the starting level
+pub fn level(game: &Game) -> usize {
+    game.options.start_level + game.total_line / 10
+}
+
+// frames between each fall, it gets faster every level
+pub fn fall_interval(level: usize) -> u64 {
+    let msec = match 1000u64.saturating_sub((level as u64 - 1) * 100) {
+        0 => 100,
+        msec => msec,
+    };
+    msec * FPS / 1000
+}
+
+// Advance the game by one frame.
+// Returns `Ok(true)` if the mino fell (or landed) in this frame.
+pub fn tick(game: &mut Game) -> Result<bool, ()> {
+    game.frame += 1;
+    game.fall_frame += 1;
+    if !game.options.gravity || game.fall_frame < fall_interval(level(game)) {
+        return Ok(false);
+    }
+    game.fall_frame = 0;
+    fall(game)?;
+    Ok(true)
+}
+
+// Move the mino down by one, landing it if it can't
+pub fn fall(game: &mut Game) -> Result<(), ()> {
+    let new_pos = Position {
+        x: game.pos.x,
+        y: game.pos.y + 1,
+    };
+    if !is_collision(&game.field, &new_pos, &game.mino) {
+        game.pos = new_pos;
+        game.rotated = false;
+        Ok(())
+    } else {
+        landing(game)
     }
 }
 
 pub fn spawn_mino(game: &mut Game) -> Result<(), ()> {
     game.pos = Position::init();
+    game.rotated = false;
+    game.fall_frame = 0;
     game.mino = game.next.pop_front().unwrap();
+    game.stats.receive(block_of(&game.mino));
 
     if let Some(next) = game.next_buf.pop_front() {
         game.next.push_back(next);
     } else {
-        game.next_buf = gen_mino_7().into();
+        game.next_buf = gen_mino_7(&mut game.rng).into();
         game.next.push_back(game.next_buf.pop_front().unwrap());
     }
 
@@ -242,28 +656,29 @@ pub fn spawn_mino(game: &mut Game) -> Result<(), ()> {
 
 pub fn gameover(game: &Game) -> ! {
     draw(game);
-    println!("Game Over!");
+    // leave the alternate screen first so the result stays in the scrollback
+    terminal::restore();
+    println!(
+        "Game Over! score: {}, {} lines",
+        game.score, game.total_line
+    );
     quit();
 }
 
 pub fn quit() -> ! {
-    println!("\x1b[?25h");
+    terminal::restore();
     std::process::exit(0);
 }
 
-#[allow(clippy::needless_range_loop)]
 pub fn rotate_right(game: &mut Game) {
-    let mut new_shape: MinoShape = Default::default();
-    for y in 0..4 {
-        for x in 0..4 {
-            new_shape[y][x] = game.mino[4 - 1 - x][y];
-        }
-    }
+    let new_shape = turn_right(&game.mino);
     if !is_collision(&game.field, &game.pos, &new_shape) {
         game.mino = new_shape;
+        game.rotated = true;
     } else if let Ok(new_pos) = super_rotation(&game.field, &game.pos, &new_shape) {
         game.pos = new_pos;
         game.mino = new_shape;
+        game.rotated = true;
     }
 }
 
@@ -277,9 +692,11 @@ pub fn rotate_left(game: &mut Game) {
     }
     if !is_collision(&game.field, &game.pos, &new_shape) {
         game.mino = new_shape;
+        game.rotated = true;
     } else if let Ok(new_pos) = super_rotation(&game.field, &game.pos, &new_shape) {
         game.pos = new_pos;
         game.mino = new_shape;
+        game.rotated = true;
     }
 }
 
@@ -298,16 +715,270 @@ pub fn hard_drop(game: &mut Game) {
 }
 
 pub fn landing(game: &mut Game) -> Result<(), ()> {
+    let tspin = is_tspin(game);
     fix_mino(game);
+    game.events.push(Event::Lock {
+        mino: game.mino,
+        pos: game.pos,
+        tspin,
+    });
+
+    let field = game.field;
     let line_count = erase_line(&mut game.field);
+    if line_count > 0 {
+        let rows: Vec<_> = (1..FIELD_HEIGHT - 2)
+            .filter(|&y| is_line_full(&field[y]))
+            .collect();
+        game.stats.garbage_cleared += rows
+            .iter()
+            .filter(|&&y| field[y].contains(&block_kind::GARBAGE))
+            .count();
+        game.events.push(Event::LineClear {
+            field: Box::new(field),
+            rows,
+            tspin,
+        });
+    }
     game.score += SCORE_TABLE[line_count];
     game.total_line += line_count;
-    spawn_mino(game)?;
+    game.stats.pieces += 1;
+    if line_count == 4 {
+        game.stats.tetrises += 1;
+    }
+
+    let perfect = line_count > 0 && is_perfect_clear(&game.field);
+    if perfect {
+        game.score += PERFECT_CLEAR_SCORE;
+        game.stats.perfect_clears += 1;
+        game.events.push(Event::PerfectClear);
+    }
+    let attack = attack(game, line_count, tspin, perfect);
+    game.stats.attack += attack;
+    let sent = cancel_garbage(game, attack);
+    if sent > 0 {
+        game.events.push(Event::Attack(sent));
+    }
+
+    // garbage only rises when the mino didn't clear any line
+    let risen = if line_count == 0 {
+        rise_garbage(game)
+    } else {
+        Ok(())
+    };
+    if risen
+        .and_then(|_| dig(game))
+        .and_then(|_| spawn_mino(game))
+        .is_err()
+    {
+        game.events.push(Event::GameOver);
+        return Err(());
+    }
     game.holded = false;
     Ok(())
 }
 
-fn ghost_pos(field: &FieldSize, pos: &Position, mino: &MinoShape) -> Position {
+// Push the field up and add a garbage line at the bottom.
+// Blocks pushed out of the top of the field are an error.
+pub fn insert_garbage(field: &mut FieldSize, hole: usize) -> Result<(), ()> {
+    let overflow = field[1][2..FIELD_WIDTH - 2]
+        .iter()
+        .any(|&block| block != block_kind::NONE);
+    for y in 1..FIELD_HEIGHT - 3 {
+        field[y] = field[y + 1];
+    }
+    let bottom = &mut field[FIELD_HEIGHT - 3];
+    for (x, block) in bottom[2..FIELD_WIDTH - 2].iter_mut().enumerate() {
+        *block = if x == hole {
+            block_kind::NONE
+        } else {
+            block_kind::GARBAGE
+        };
+    }
+    if overflow {
+        Err(())
+    } else {
+        Ok(())
+    }
+}
+
+// column of the hole for the next garbage line
+pub fn next_garbage_hole(game: &mut Game) -> usize {
+    if game.garbage_rng.gen_range(0..100) < game.options.messiness {
+        game.garbage_hole = game.garbage_rng.gen_range(0..FIELD_WIDTH - 4);
+    }
+    game.garbage_hole
+}
+
+// keep `dig_rows` garbage lines on the field until the goal has been dealt
+fn dig(game: &mut Game) -> Result<(), ()> {
+    let goal = match game.options.goal {
+        Goal::Garbage(lines) => lines,
+        _ => usize::MAX,
+    };
+    let on_field = game
+        .field
+        .iter()
+        .filter(|row| row.contains(&block_kind::GARBAGE))
+        .count();
+    let count = game
+        .options
+        .dig_rows
+        .saturating_sub(on_field)
+        .min(goal.saturating_sub(game.garbage_dealt));
+    for _ in 0..count {
+        let hole = next_garbage_hole(game);
+        insert_garbage(&mut game.field, hole)?;
+        game.garbage_dealt += 1;
+    }
+    Ok(())
+}
+
+// Garbage lines sent by a clear, counting combo, back-to-back and perfect clear.
+// Updates the combo and back-to-back state of the game.
+fn attack(game: &mut Game, line_count: usize, tspin: bool, perfect: bool) -> usize {
+    if line_count == 0 {
+        game.combo = 0;
+        return 0;
+    }
+
+    let mut attack = match (tspin, line_count) {
+        (true, line_count) => line_count * 2,
+        (false, 4) => 4,
+        (false, line_count) => line_count - 1,
+    };
+
+    let difficult = tspin || line_count == 4;
+    if difficult && game.b2b {
+        attack += 1;
+    }
+    game.b2b = difficult;
+
+    attack += COMBO_TABLE[game.combo.min(COMBO_TABLE.len() - 1)];
+    game.combo += 1;
+
+    if perfect {
+        attack += PERFECT_CLEAR_ATTACK;
+    }
+    attack
+}
+
+pub fn is_perfect_clear(field: &FieldSize) -> bool {
+    field[..FIELD_HEIGHT - 2].iter().all(|row| {
+        row[2..FIELD_WIDTH - 2]
+            .iter()
+            .all(|&block| block == block_kind::NONE || block == block_kind::WALL)
+    })
+}
+
+// A field read from a file or the network: known blocks, and the walls where they belong
+pub fn is_valid_field(field: &FieldSize) -> bool {
+    field
+        .iter()
+        .flatten()
+        .zip(EMPTY_FIELD.iter().flatten())
+        .all(|(&block, &empty)| block < COLOR_TABLE.len() && (empty != W || block == empty))
+}
+
+// queue garbage lines sent by the opponent
+pub fn receive_garbage(game: &mut Game, lines: usize) {
+    // more than a field of garbage tops out all the same
+    let lines = lines.min(FIELD_HEIGHT.saturating_sub(incoming_lines(game)));
+    if lines == 0 {
+        return;
+    }
+    let hole = next_garbage_hole(game);
+    game.incoming.push_back(Garbage { lines, hole });
+}
+
+// lines waiting in the incoming queue
+pub fn incoming_lines(game: &Game) -> usize {
+    game.incoming
+        .iter()
+        .fold(0, |lines, garbage| lines.saturating_add(garbage.lines))
+}
+
+// Cancel the incoming garbage with the attack, oldest first.
+// Returns the attack left to send.
+fn cancel_garbage(game: &mut Game, mut attack: usize) -> usize {
+    while attack > 0 {
+        let Some(garbage) = game.incoming.front_mut() else {
+            break;
+        };
+        let cancelled = attack.min(garbage.lines);
+        garbage.lines -= cancelled;
+        attack -= cancelled;
+        if garbage.lines == 0 {
+            game.incoming.pop_front();
+        }
+    }
+    attack
+}
+
+// raise the incoming garbage from the bottom, up to `GARBAGE_CAP` lines
+fn rise_garbage(game: &mut Game) -> Result<(), ()> {
+    let mut risen = 0;
+    while risen < GARBAGE_CAP {
+        let Some(garbage) = game.incoming.front_mut() else {
+            break;
+        };
+        insert_garbage(&mut game.field, garbage.hole)?;
+        garbage.lines -= 1;
+        risen += 1;
+        if garbage.lines == 0 {
+            game.incoming.pop_front();
+        }
+    }
+    Ok(())
+}
+
+// T-spin by the 3-corner rule: the last move was a rotation of T
+// and at least 3 of the 4 corners around its center are occupied
+fn is_tspin(game: &Game) -> bool {
+    if !game.rotated || block_of(&game.mino) != block_kind::T {
+        return false;
+    }
+
+    // the center is the only block of T with 3 neighbours
+    let is_block = |x: usize, y: usize| {
+        game.mino
+            .get(y)
+            .and_then(|row| row.get(x))
+            .is_some_and(|&block| block != block_kind::NONE)
+    };
+    let center = (0..4)
+        .flat_map(|y| (0..4).map(move |x| (x, y)))
+        .find(|&(x, y)| {
+            is_block(x, y)
+                && [
+                    is_block(x + 1, y),
+                    is_block(x.wrapping_sub(1), y),
+                    is_block(x, y + 1),
+                    is_block(x, y.wrapping_sub(1)),
+                ]
+                .iter()
+                .filter(|&&neighbour| neighbour)
+                .count()
+                    == 3
+        });
+    let Some((x, y)) = center else {
+        return false;
+    };
+
+    let (x, y) = (game.pos.x + x, game.pos.y + y);
+    [(-1, -1), (1, -1), (-1, 1), (1, 1)]
+        .iter()
+        .filter(|&&(dx, dy)| {
+            let corner = x
+                .checked_add_signed(dx)
+                .zip(y.checked_add_signed(dy))
+                .and_then(|(x, y)| game.field.get(y)?.get(x));
+            corner.is_some_and(|&block| block != block_kind::NONE)
+        })
+        .count()
+        >= 3
+}
+
+pub fn ghost_pos(field: &FieldSize, pos: &Position, mino: &MinoShape) -> Position {
     let mut ghost_pos = *pos;
 
     while {
@@ -352,16 +1023,244 @@ fn super_rotation(field: &FieldSize, pos: &Position, mino: &MinoShape) -> Result
 }
 
 pub fn hold(game: &mut Game) {
-    if game.holded {
+    if !game.options.hold || (game.holded && !game.options.infinite_hold) {
         return;
     }
     if let Some(mut hold) = game.hold {
         std::mem::swap(&mut hold, &mut game.mino);
         game.hold = Some(hold);
         game.pos = Position::init();
+        game.rotated = false;
     } else {
         game.hold = Some(game.mino);
         spawn_mino(game).ok();
     }
     game.holded = true;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_garbage() {
+        let mut field = EMPTY_FIELD;
+        field[FIELD_HEIGHT - 3][2] = block_kind::I;
+        assert!(insert_garbage(&mut field, 3).is_ok());
+        assert_eq!(field[FIELD_HEIGHT - 4][2], block_kind::I);
+        assert_eq!(field[FIELD_HEIGHT - 3][2 + 3], block_kind::NONE);
+        assert_eq!(
+            field[FIELD_HEIGHT - 3]
+                .iter()
+                .filter(|&&block| block == block_kind::GARBAGE)
+                .count(),
+            FIELD_WIDTH - 5
+        );
+
+        let mut field = EMPTY_FIELD;
+        field[1][5] = block_kind::T;
+        assert!(insert_garbage(&mut field, 0).is_err());
+    }
+
+    #[test]
+    fn test_attack() {
+        let mut game = Game::with_options(Options::default());
+        // (lines, tspin, perfect clear, expected attack)
+        let tests = [
+            (1, false, false, 0),
+            (4, false, false, 4), // combo 1
+            (4, false, false, 6), // back-to-back, combo 2
+            (2, true, false, 6),  // back-to-back, combo 3
+            (0, false, false, 0), // combo broken
+            (4, false, false, 5), // back-to-back is kept without clears
+            (2, false, false, 1), // back-to-back broken, combo 1
+            (0, false, false, 0),
+            (4, false, false, 4),
+            (0, false, false, 0),
+            (1, false, true, 10), // perfect clear
+        ];
+        for (line_count, tspin, perfect, expect) in tests {
+            assert_eq!(attack(&mut game, line_count, tspin, perfect), expect);
+        }
+    }
+
+    #[test]
+    fn test_garbage_cancel_and_rise() {
+        let mut game = Game::with_options(Options::default());
+        receive_garbage(&mut game, 3);
+        receive_garbage(&mut game, 2);
+        assert_eq!(incoming_lines(&game), 5);
+
+        assert_eq!(cancel_garbage(&mut game, 4), 0);
+        assert_eq!(incoming_lines(&game), 1);
+        assert_eq!(cancel_garbage(&mut game, 3), 2);
+        assert_eq!(incoming_lines(&game), 0);
+
+        receive_garbage(&mut game, 10);
+        assert!(rise_garbage(&mut game).is_ok());
+        assert_eq!(incoming_lines(&game), 10 - GARBAGE_CAP);
+        let garbage_rows = game
+            .field
+            .iter()
+            .filter(|row| row.contains(&block_kind::GARBAGE))
+            .count();
+        assert_eq!(garbage_rows, GARBAGE_CAP);
+
+        // attacks add up to a field at most
+        for _ in 0..3 {
+            receive_garbage(&mut game, usize::MAX);
+        }
+        assert_eq!(incoming_lines(&game), FIELD_HEIGHT);
+    }
+
+    #[test]
+    fn test_tspin_of_a_broken_shape() {
+        // a T shape coming from the network is checked, the corners are anyway
+        let mut game = Game::with_options(Options::default());
+        game.mino = [
+            [block_kind::T, block_kind::T, block_kind::T, 0],
+            [0, block_kind::T, 0, 0],
+            [0; 4],
+            [0; 4],
+        ];
+        game.pos = Position { x: 0, y: 0 };
+        game.rotated = true;
+        assert!(!is_tspin(&game));
+    }
+
+    #[test]
+    fn test_line_goal() {
+        let mut game = Game::with_options(Options {
+            goal: Goal::Lines(40),
+            ..Default::default()
+        });
+        game.total_line = 39;
+        assert!(!goal_reached(&game));
+        game.total_line = 40;
+        assert!(goal_reached(&game));
+    }
+
+    #[test]
+    fn test_time_limit() {
+        let mut game = Game::with_options(Options {
+            goal: Goal::Time(2 * FPS),
+            ..Default::default()
+        });
+        for _ in 1..2 * FPS {
+            tick(&mut game).unwrap();
+        }
+        assert!(!goal_reached(&game));
+        tick(&mut game).unwrap();
+        assert!(goal_reached(&game));
+    }
+
+    #[test]
+    fn test_level() {
+        let mut game = Game::with_options(Options {
+            goal: Goal::Level(6),
+            start_level: 5,
+            ..Default::default()
+        });
+        assert_eq!(level(&game), 5);
+        assert_eq!(lines_to_goal(&game), 20);
+        game.total_line = 19;
+        assert_eq!(level(&game), 6);
+        assert_eq!(lines_to_goal(&game), 1);
+        assert!(!goal_reached(&game));
+        // the goal is to finish the level, not to reach it
+        game.total_line = 20;
+        assert_eq!(level(&game), 7);
+        assert!(goal_reached(&game));
+
+        // falls get faster every level, down to 100ms
+        assert_eq!(fall_interval(1), FPS);
+        assert!(fall_interval(5) < fall_interval(4));
+        assert_eq!(fall_interval(10), FPS / 10);
+        assert_eq!(fall_interval(20), FPS / 10);
+    }
+
+    #[test]
+    fn test_same_seed_same_game() {
+        let options = Options {
+            seed: Some(42),
+            dig_rows: 5,
+            ..Default::default()
+        };
+        let game1 = Game::with_options(options);
+        let game2 = Game::with_options(options);
+        assert_eq!(game1.field, game2.field);
+        assert_eq!(game1.mino, game2.mino);
+        assert_eq!(game1.next, game2.next);
+    }
+
+    #[test]
+    fn test_practice_options() {
+        let mut game = Game::with_options(Options {
+            seed: Some(1),
+            gravity: false,
+            infinite_hold: true,
+            ..Default::default()
+        });
+        let (mino, pos) = (game.mino, game.pos);
+        for _ in 0..FPS * 10 {
+            assert_eq!(tick(&mut game), Ok(false));
+        }
+        assert_eq!(game.pos, pos);
+
+        hold(&mut game);
+        hold(&mut game);
+        assert_eq!(game.mino, mino);
+    }
+
+    #[test]
+    fn test_with_setup() {
+        let options = Options {
+            seed: Some(1),
+            ..Default::default()
+        };
+        let random = Game::with_options(options);
+        let mut field = EMPTY_FIELD;
+        field[FIELD_HEIGHT - 3][2..11].fill(block_kind::GARBAGE);
+        let setup = Setup {
+            field,
+            minos: vec![MinoKind::T, MinoKind::I],
+            hold: Some(MinoKind::O),
+        };
+        let game = Game::with_setup(options, &setup).unwrap();
+        assert_eq!(game.field, field);
+        assert_eq!(game.mino, MINOS[MinoKind::T as usize]);
+        assert_eq!(game.next[0], MINOS[MinoKind::I as usize]);
+        assert_eq!(game.next[1], random.mino);
+        assert_eq!(game.hold, Some(MINOS[MinoKind::O as usize]));
+        assert_eq!(game.stats.received[MinoKind::T as usize], 1);
+
+        let mut full = EMPTY_FIELD;
+        full[1][2..12].fill(block_kind::GARBAGE);
+        let setup = Setup {
+            field: full,
+            ..setup
+        };
+        assert!(Game::with_setup(options, &setup).is_err());
+    }
+
+    #[test]
+    fn test_perfect_clear() {
+        let mut field = EMPTY_FIELD;
+        field[FIELD_HEIGHT - 3][2..5].fill(block_kind::GARBAGE);
+        field[FIELD_HEIGHT - 3][9..12].fill(block_kind::GARBAGE);
+        let setup = Setup {
+            field,
+            minos: vec![MinoKind::I],
+            hold: None,
+        };
+        let mut game = Game::with_setup(Options::default(), &setup).unwrap();
+        handle_input(&mut game, Input::HardDrop).unwrap();
+        assert!(is_perfect_clear(&game.field));
+        assert!(game
+            .events
+            .iter()
+            .any(|event| matches!(event, Event::PerfectClear)));
+        assert_eq!(game.score, SCORE_TABLE[1] + PERFECT_CLEAR_SCORE);
+        assert_eq!(game.stats.perfect_clears, 1);
+    }
+}
@@ -7,6 +7,7 @@ use std::ops::Index;
 
 use crate::ai::eval;
 use crate::game::*;
+use crate::spectate::publish;
 use getch_rs::{Getch, Key};
 use std::thread;
 
@@ -59,21 +60,26 @@ impl Distribution<Individual> for Standard {
     }
 }
 
-pub fn learning() -> ! {
-    let _ = thread::spawn(|| {
+pub fn learning(options: Options) -> ! {
+    let _ = thread::spawn(move || {
         let mut inds = rand::random::<[Individual; POPULATION]>();
         for gen in 1..=GENERATION_MAX {
             println!("{gen} generation:");
             thread::scope(|s| {
                 for (i, ind) in inds.iter_mut().enumerate() {
                     s.spawn(move || {
-                        let mut game = Game::new();
+                        let mut game = Game::with_options(options);
                         while game.total_line < LINE_COUNT_MAX {
                             let elite = eval(&game, &ind.geno);
                             game = elite;
                             if landing(&mut game).is_err() {
                                 break;
                             }
+                            game.events.clear();
+                            // spectators watch the first individual
+                            if i == 0 {
+                                publish(&game);
+                            }
                         }
                         ind.score = game.score;
                         println!("{i}: {:?} => {}", ind.geno, game.score);
@@ -91,7 +97,7 @@ pub fn learning() -> ! {
 
     let g = Getch::new();
     loop {
-        if let Ok(Key::Char('q')) = g.getch() {
+        if let Ok(Key::Char('q') | Key::Ctrl('c')) = g.getch() {
             quit();
         }
     }
@@ -134,7 +140,6 @@ fn mutation(inds: &[Individual]) -> [GenoSeq; MUTATION_LEN] {
     let mut rng = rand::thread_rng();
     genos.shuffle(&mut rng);
     for geno in genos.iter_mut().take(MUTATION_LEN) {
-        let mut geno = *geno;
         geno[rng.gen_range(0..4)] = rand::random();
     }
     genos[..MUTATION_LEN].try_into().unwrap()
@@ -1,16 +1,71 @@
 mod ai;
+mod animation;
 mod block;
+mod finesse;
+mod fumen;
 mod game;
 mod genetic_algorigthm;
 mod mino;
+mod net;
+mod notation;
+mod opener;
 mod play;
-use clap::{Parser, Subcommand};
+mod puzzle;
+mod records;
+mod replay;
+mod save;
+mod spectate;
+mod stats;
+mod terminal;
+mod versus;
+use clap::{error::ErrorKind, CommandFactory, Parser, Subcommand};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
     #[command(subcommand)]
     mode: Option<Mode>,
+
+    /// Disable line clear, lock and game over animations
+    #[arg(long, global = true)]
+    no_animation: bool,
+
+    /// Number of visible next minos
+    #[arg(long, global = true, default_value_t = game::NEXT_LENGTH as u8,
+          value_parser = clap::value_parser!(u8).range(0..=game::NEXT_LENGTH_MAX as i64))]
+    next: u8,
+
+    /// Disable hold
+    #[arg(long, global = true)]
+    no_hold: bool,
+
+    /// Seed of the random minos and garbage
+    #[arg(long, global = true)]
+    seed: Option<u64>,
+
+    /// Let spectators on this machine watch the game on this port (normal, auto, learning and the single player modes)
+    #[arg(long, global = true, value_name = "PORT")]
+    broadcast: Option<u16>,
+
+    /// Name in the high score tables, the login name by default
+    #[arg(long, global = true)]
+    name: Option<String>,
+
+    /// Start from this field, a fumen or a file of rows of `.` `X` `IOSZJLT` (normal and the single player modes)
+    #[arg(long, global = true)]
+    field: Option<String>,
+
+    /// Minos to start with, the current one first, e.g. TIOS. The random ones come after them
+    #[arg(long, global = true, value_name = "MINOS")]
+    queue: Option<String>,
+
+    /// Mino in the hold at the start
+    #[arg(long, global = true, value_name = "MINO")]
+    hold: Option<String>,
+
+    /// Resume a game saved with `s`, in the mode it was saved in
+    #[arg(long, global = true, value_name = "FILE")]
+    load: Option<std::path::PathBuf>,
 }
 
 #[derive(Subcommand)]
@@ -18,19 +73,352 @@ enum Mode {
     Normal,
     Auto,
     Learning,
+    /// Clear the lines as fast as possible
+    Sprint {
+        /// Lines to clear
+        #[arg(long, default_value_t = 40,
+              value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+        lines: usize,
+    },
+    /// Score as much as possible before the time runs out
+    Ultra {
+        /// Time limit in seconds
+        #[arg(long, default_value_t = 120,
+              value_parser = clap::value_parser!(u64).range(1..))]
+        seconds: u64,
+    },
+    /// Survive until the final level
+    Marathon {
+        /// Starting level
+        #[arg(long, default_value_t = 1,
+              value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..=15))]
+        level: usize,
+        /// The game ends after this level
+        #[arg(long, default_value_t = 15,
+              value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+        end_level: usize,
+        /// End after clearing this many lines instead of at the final level
+        #[arg(long, conflicts_with = "end_level",
+              value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+        lines: Option<usize>,
+    },
+    /// Dig through garbage lines as fast as possible
+    Dig {
+        /// Garbage lines to clear
+        #[arg(long, default_value_t = 18,
+              value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+        lines: usize,
+        /// Garbage lines on the field at a time
+        #[arg(long, default_value_t = 10,
+              value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..=18))]
+        rows: usize,
+        /// Chance in % that the hole moves between garbage lines
+        #[arg(long, default_value_t = 100,
+              value_parser = clap::value_parser!(u8).range(0..=100))]
+        messiness: u8,
+    },
+    /// No gravity, unlimited hold and undo, for drilling openers
+    Practice,
+    /// Solve the puzzles of a pack one after another
+    Puzzle {
+        /// Puzzle pack, see README.md for the format
+        file: std::path::PathBuf,
+    },
+    /// Practice the first perfect clear with bag-aligned queues
+    Pc,
+    /// Place each mino where it is shown with the fewest moves
+    Finesse,
+    /// Build the first bag of an opener where it is shown
+    Opener {
+        /// tki, dt (DT cannon) or pco
+        #[arg(default_value = "tki", value_parser = ["tki", "dt", "pco"])]
+        name: String,
+    },
+    /// Two players on one keyboard sending garbage to each other
+    Versus,
+    /// Play against the AI, sending garbage to each other
+    Bot {
+        /// Pieces the bot places per second
+        #[arg(long, default_value_t = 1.0, value_parser = parse_pps)]
+        pps: f64,
+        /// Weights of the bot: line, height max, height diff, dead space
+        #[arg(long, default_value = "100,1,10,100", value_parser = parse_weight)]
+        weight: genetic_algorigthm::GenoSeq,
+    },
+    /// Wait for an opponent to play versus over the network
+    Serve {
+        /// Port to listen on
+        #[arg(long, default_value_t = net::DEFAULT_PORT)]
+        port: u16,
+    },
+    /// Play versus against a server, with its seed and options
+    Connect {
+        /// host:port of the server, or just the host for the default port
+        addr: String,
+    },
+    /// Play a saved replay again
+    Replay {
+        /// Replay file, saved in $XDG_DATA_HOME/tetris/replays
+        file: std::path::PathBuf,
+    },
+    /// Show the high score tables
+    Scores {
+        /// Only this table, e.g. normal, sprint-40 or ultra-120
+        board: Option<String>,
+    },
+    /// Print a field as rows and as a fumen, to share it
+    Field {
+        /// A fumen, or a file of rows of `.` `X` `IOSZJLT`
+        field: String,
+    },
+    /// Watch a game started with --broadcast
+    Spectate {
+        /// host:port of the broadcast, or just the host for the default port
+        addr: String,
+    },
+}
+
+fn parse_pps(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(pps) if pps > 0.0 && pps <= game::FPS as f64 => Ok(pps),
+        Ok(_) => Err(format!("must be more than 0 and at most {}", game::FPS)),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn parse_weight(s: &str) -> Result<genetic_algorigthm::GenoSeq, String> {
+    let weight = s
+        .split(',')
+        .map(|w| w.trim().parse::<u8>().map_err(|e| format!("{w:?}: {e}")))
+        .collect::<Result<Vec<_>, _>>()?;
+    weight
+        .try_into()
+        .map_err(|_| "expected 4 comma separated numbers".to_string())
 }
 
 fn main() {
     let cli = Cli::parse();
+    let options = game::Options {
+        next_length: cli.next as usize,
+        hold: !cli.no_hold,
+        seed: cli.seed,
+        ..Default::default()
+    };
+    let setup = notation::load_setup(
+        cli.field.as_deref(),
+        cli.queue.as_deref(),
+        cli.hold.as_deref(),
+    )
+    .unwrap_or_else(|e| {
+        Cli::command().error(ErrorKind::ValueValidation, e).exit();
+    });
+    if let Some(setup) = &setup {
+        if game::Game::with_setup(options, setup).is_err() {
+            Cli::command()
+                .error(
+                    ErrorKind::ValueValidation,
+                    "the first mino doesn't fit in the field",
+                )
+                .exit();
+        }
+    }
+    if let Some(name) = cli.name {
+        records::set_player_name(name);
+    }
+    if let Some(port) = cli.broadcast {
+        spectate::broadcast(port).unwrap_or_else(|e| {
+            Cli::command().error(ErrorKind::Io, e).exit();
+        });
+    }
+    if let Some(file) = cli.load {
+        let save = save::Save::load(&file).unwrap_or_else(|e| {
+            Cli::command()
+                .error(ErrorKind::Io, format!("{}: {e}", file.display()))
+                .exit();
+        });
+        let _terminal = terminal::Terminal::new();
+        play::resume(save, !cli.no_animation);
+    }
     match cli.mode {
         None | Some(Mode::Normal) => {
-            play::normal();
+            let _terminal = terminal::Terminal::new();
+            play::normal(play::Mode::Normal, options, setup, !cli.no_animation);
         }
         Some(Mode::Auto) => {
-            play::auto();
+            let _terminal = terminal::Terminal::new();
+            play::auto(options);
+        }
+        Some(Mode::Sprint { lines }) => {
+            let _terminal = terminal::Terminal::new();
+            let options = game::Options {
+                goal: game::Goal::Lines(lines),
+                ..options
+            };
+            play::normal(play::Mode::Sprint, options, setup, !cli.no_animation);
+        }
+        Some(Mode::Ultra { seconds }) => {
+            let _terminal = terminal::Terminal::new();
+            let options = game::Options {
+                goal: game::Goal::Time(seconds * game::FPS),
+                ..options
+            };
+            play::normal(play::Mode::Ultra, options, setup, !cli.no_animation);
+        }
+        Some(Mode::Marathon {
+            level,
+            end_level,
+            lines,
+        }) => {
+            if lines.is_none() && end_level < level {
+                Cli::command()
+                    .error(
+                        ErrorKind::ValueValidation,
+                        "--end-level must not be lower than --level",
+                    )
+                    .exit();
+            }
+            let _terminal = terminal::Terminal::new();
+            let options = game::Options {
+                goal: match lines {
+                    Some(lines) => game::Goal::Lines(lines),
+                    None => game::Goal::Level(end_level),
+                },
+                start_level: level,
+                ..options
+            };
+            play::normal(play::Mode::Marathon, options, setup, !cli.no_animation);
+        }
+        Some(Mode::Dig {
+            lines,
+            rows,
+            messiness,
+        }) => {
+            let _terminal = terminal::Terminal::new();
+            let options = game::Options {
+                goal: game::Goal::Garbage(lines),
+                dig_rows: rows,
+                messiness,
+                ..options
+            };
+            play::normal(play::Mode::Dig, options, setup, !cli.no_animation);
+        }
+        Some(Mode::Practice) => {
+            let _terminal = terminal::Terminal::new();
+            let options = game::Options {
+                gravity: false,
+                infinite_hold: true,
+                ..options
+            };
+            play::normal(play::Mode::Practice, options, setup, !cli.no_animation);
+        }
+        Some(Mode::Puzzle { file }) => {
+            let puzzles = puzzle::load(&file).unwrap_or_else(|e| {
+                Cli::command().error(ErrorKind::Io, e).exit();
+            });
+            let _terminal = terminal::Terminal::new();
+            puzzle::puzzle(puzzles, options, !cli.no_animation);
+        }
+        Some(Mode::Pc) => {
+            let _terminal = terminal::Terminal::new();
+            puzzle::perfect_clear(options, !cli.no_animation);
+        }
+        Some(Mode::Finesse) => {
+            let _terminal = terminal::Terminal::new();
+            finesse::trainer(options, !cli.no_animation);
+        }
+        Some(Mode::Opener { name }) => {
+            let opener = opener::Opener::get(&name).unwrap();
+            let _terminal = terminal::Terminal::new();
+            opener::trainer(opener, options, !cli.no_animation);
+        }
+        Some(Mode::Versus) => {
+            let _terminal = terminal::Terminal::new();
+            versus::versus(options, !cli.no_animation, versus::Opponent::Human);
+        }
+        Some(Mode::Bot { pps, weight }) => {
+            let _terminal = terminal::Terminal::new();
+            let bot = versus::Bot { pps, weight };
+            versus::versus(options, !cli.no_animation, versus::Opponent::Bot(bot));
+        }
+        Some(Mode::Serve { port }) => {
+            let (remote, options) = net::serve(port, options).unwrap_or_else(|e| {
+                Cli::command().error(ErrorKind::Io, e).exit();
+            });
+            let _terminal = terminal::Terminal::new();
+            versus::versus(options, !cli.no_animation, versus::Opponent::Remote(remote));
+        }
+        Some(Mode::Connect { addr }) => {
+            let (remote, options) = net::connect(&addr, options).unwrap_or_else(|e| {
+                Cli::command().error(ErrorKind::Io, e).exit();
+            });
+            let _terminal = terminal::Terminal::new();
+            versus::versus(options, !cli.no_animation, versus::Opponent::Remote(remote));
+        }
+        Some(Mode::Replay { file }) => {
+            let replay = replay::Replay::load(&file).unwrap_or_else(|e| {
+                Cli::command()
+                    .error(ErrorKind::Io, format!("{}: {e}", file.display()))
+                    .exit();
+            });
+            let _terminal = terminal::Terminal::new();
+            replay::playback(replay);
+        }
+        Some(Mode::Scores { board }) => {
+            let boards = match board {
+                // only the tables there are, a board name never leads to another file
+                Some(board) if !records::boards().contains(&board) => {
+                    Cli::command()
+                        .error(
+                            ErrorKind::InvalidValue,
+                            format!("no high score table {board:?}"),
+                        )
+                        .exit();
+                }
+                Some(board) => vec![board],
+                None => records::boards(),
+            };
+            if boards.is_empty() {
+                println!("No high scores yet");
+            }
+            for board in boards {
+                let rank = records::rank(&board);
+                println!("{board}");
+                for (i, score) in records::load_scores(&board).iter().enumerate() {
+                    println!(
+                        "  {}  seed {}",
+                        records::format_score(i, rank, score),
+                        score.seed
+                    );
+                }
+                println!();
+            }
+        }
+        Some(Mode::Field { field }) => {
+            let setup =
+                notation::load_setup(Some(&field), cli.queue.as_deref(), cli.hold.as_deref())
+                    .unwrap_or_else(|e| {
+                        Cli::command().error(ErrorKind::ValueValidation, e).exit();
+                    })
+                    .unwrap_or_default();
+            print!("{}", notation::format_field(&setup.field));
+            if !setup.minos.is_empty() {
+                println!("queue {}", notation::format_minos(&setup.minos));
+            }
+            if let Some(hold) = setup.hold {
+                println!("hold {}", notation::format_minos(&[hold]));
+            }
+            println!("{}", fumen::encode(&setup));
+        }
+        Some(Mode::Spectate { addr }) => {
+            let stream = net::open(&addr, spectate::DEFAULT_PORT).unwrap_or_else(|e| {
+                Cli::command().error(ErrorKind::Io, e).exit();
+            });
+            let _terminal = terminal::Terminal::new();
+            spectate::spectate(stream);
         }
         Some(Mode::Learning) => {
-            genetic_algorigthm::learning();
+            let _terminal = terminal::Terminal::raw();
+            genetic_algorigthm::learning(options);
         }
     }
 }
@@ -1,13 +1,26 @@
-use crate::block::block_kind::{I, J, L, O, S, T, Z};
+use crate::block::block_kind::{self, I, J, L, O, S, T, Z};
+use crate::block::BlockColor;
 use rand::{
     distributions::{Distribution, Standard},
     seq::SliceRandom,
-    thread_rng, Rng,
+    Rng,
 };
+use serde::{Deserialize, Serialize};
 
 const MINO_KIND_MAX: usize = 7;
 
-#[derive(Clone, Copy)]
+// in the order of `MINOS`
+pub const MINO_KINDS: [MinoKind; MINO_KIND_MAX] = [
+    MinoKind::I,
+    MinoKind::O,
+    MinoKind::S,
+    MinoKind::Z,
+    MinoKind::J,
+    MinoKind::L,
+    MinoKind::T,
+];
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
 pub enum MinoKind {
     I,
     O,
@@ -86,17 +99,41 @@ pub const MINOS: [MinoShape; MINO_KIND_MAX] = [
     ],
 ];
 
-pub fn gen_mino_7() -> [MinoShape; MINO_KIND_MAX] {
-    let mut rng = thread_rng();
-    let mut queue = [
-        MinoKind::I,
-        MinoKind::O,
-        MinoKind::S,
-        MinoKind::Z,
-        MinoKind::J,
-        MinoKind::L,
-        MinoKind::T,
-    ];
-    queue.shuffle(&mut rng);
+// block kind the mino is made of
+pub fn block_of(mino: &MinoShape) -> BlockColor {
+    mino.iter()
+        .flatten()
+        .copied()
+        .find(|&block| block != block_kind::NONE)
+        .unwrap_or(block_kind::NONE)
+}
+
+// the mino turned clockwise in its 4x4 box
+#[allow(clippy::needless_range_loop)]
+pub fn turn_right(mino: &MinoShape) -> MinoShape {
+    let mut new_shape: MinoShape = Default::default();
+    for y in 0..4 {
+        for x in 0..4 {
+            new_shape[y][x] = mino[4 - 1 - x][y];
+        }
+    }
+    new_shape
+}
+
+// whether the shape is the mino of this kind in one of its rotations
+pub fn is_rotation_of(mino: &MinoShape, kind: MinoKind) -> bool {
+    let mut shape = MINOS[kind as usize];
+    for _ in 0..4 {
+        if shape == *mino {
+            return true;
+        }
+        shape = turn_right(&shape);
+    }
+    false
+}
+
+pub fn gen_mino_7<R: Rng + ?Sized>(rng: &mut R) -> [MinoShape; MINO_KIND_MAX] {
+    let mut queue = MINO_KINDS;
+    queue.shuffle(rng);
     queue.map(|mino| MINOS[mino as usize])
 }
@@ -0,0 +1,325 @@
+use crate::block::block_kind;
+use crate::game::*;
+use crate::mino::{is_rotation_of, MinoShape, MINO_KINDS};
+use crate::notation::{block_char, char_block};
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::str::FromStr;
+
+// Bumped whenever a message changes, both sides must speak the same version
+pub const PROTOCOL_VERSION: u32 = 2;
+pub const DEFAULT_PORT: u16 = 7878;
+
+// rows sent in `FIELD`, from the spawn row down to the last row above the floor
+const FIELD_ROWS: usize = FIELD_HEIGHT - 2;
+
+// One line of the versus protocol, see README.md
+#[derive(Clone, PartialEq, Debug)]
+pub enum Message {
+    Hello(u32),
+    Options {
+        seed: u64,
+        next_length: usize,
+        hold: bool,
+    },
+    Ready,
+    Hold,
+    Lock {
+        mino: MinoShape,
+        pos: Position,
+        tspin: bool,
+    },
+    Field(Box<FieldSize>),
+    Attack(usize),
+    Lose,
+}
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Message::Hello(version) => write!(f, "HELLO {version}"),
+            Message::Options {
+                seed,
+                next_length,
+                hold,
+            } => write!(f, "OPTIONS {seed} {next_length} {}", *hold as u8),
+            Message::Ready => write!(f, "READY"),
+            Message::Hold => write!(f, "HOLD"),
+            Message::Lock { mino, pos, tspin } => {
+                let mut cells = 0u16;
+                let mut block = block_kind::NONE;
+                for &cell in mino.iter().flatten() {
+                    cells = cells << 1 | (cell != block_kind::NONE) as u16;
+                    block = block.max(cell);
+                }
+                write!(
+                    f,
+                    "LOCK {} {cells:04x} {} {} {}",
+                    block_char(block),
+                    pos.x,
+                    pos.y,
+                    *tspin as u8
+                )
+            }
+            Message::Field(field) => {
+                let rows = field[..FIELD_ROWS]
+                    .iter()
+                    .flat_map(|row| &row[2..FIELD_WIDTH - 2])
+                    .map(|&block| block_char(block))
+                    .collect::<String>();
+                write!(f, "FIELD {rows}")
+            }
+            Message::Attack(lines) => write!(f, "ATTACK {lines}"),
+            Message::Lose => write!(f, "LOSE"),
+        }
+    }
+}
+
+impl FromStr for Message {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Message, String> {
+        let mut words = line.split_whitespace();
+        let mut arg = || words.next().ok_or(format!("missing argument: {line}"));
+        fn num<T: FromStr>(word: &str) -> Result<T, String> {
+            word.parse().map_err(|_| format!("not a number: {word}"))
+        }
+        fn flag(word: &str) -> Result<bool, String> {
+            match word {
+                "0" => Ok(false),
+                "1" => Ok(true),
+                _ => Err(format!("not 0 or 1: {word}")),
+            }
+        }
+
+        let message = match arg()? {
+            "HELLO" => Message::Hello(num(arg()?)?),
+            "OPTIONS" => Message::Options {
+                seed: num(arg()?)?,
+                next_length: num::<usize>(arg()?)?.min(NEXT_LENGTH_MAX),
+                hold: flag(arg()?)?,
+            },
+            "READY" => Message::Ready,
+            "HOLD" => Message::Hold,
+            "LOCK" => {
+                let block = arg()?.chars().next().and_then(char_block);
+                let block = block
+                    .filter(|block| (block_kind::I..=block_kind::T).contains(block))
+                    .ok_or(format!("unknown mino: {line}"))?;
+                let cells = u16::from_str_radix(arg()?, 16).map_err(|e| e.to_string())?;
+                let mut mino = MinoShape::default();
+                for (i, cell) in mino.iter_mut().flatten().enumerate() {
+                    if cells >> (15 - i) & 1 == 1 {
+                        *cell = block;
+                    }
+                }
+                let pos = Position {
+                    x: num(arg()?)?,
+                    y: num(arg()?)?,
+                };
+                if !is_rotation_of(&mino, MINO_KINDS[block - block_kind::I]) {
+                    return Err(format!("not a mino: {line}"));
+                }
+                let cells = mino_cells(&mino, &pos);
+                if cells
+                    .iter()
+                    .any(|&(x, y)| x >= FIELD_WIDTH || y >= FIELD_HEIGHT)
+                {
+                    return Err(format!("out of the field: {line}"));
+                }
+                Message::Lock {
+                    mino,
+                    pos,
+                    tspin: flag(arg()?)?,
+                }
+            }
+            "FIELD" => {
+                let rows = arg()?.chars().map(char_block).collect::<Option<Vec<_>>>();
+                let rows = rows.ok_or(format!("unknown block: {line}"))?;
+                if rows.len() != FIELD_ROWS * (FIELD_WIDTH - 4) {
+                    return Err(format!("wrong field size: {line}"));
+                }
+                let mut field = Box::new(EMPTY_FIELD);
+                for (row, blocks) in field.iter_mut().zip(rows.chunks(FIELD_WIDTH - 4)) {
+                    row[2..FIELD_WIDTH - 2].copy_from_slice(blocks);
+                }
+                Message::Field(field)
+            }
+            // more lines than the field are no more harmful
+            "ATTACK" => Message::Attack(num::<usize>(arg()?)?.min(FIELD_HEIGHT)),
+            "LOSE" => Message::Lose,
+            _ => return Err(format!("unknown message: {line}")),
+        };
+        Ok(message)
+    }
+}
+
+// The other side of a network versus
+pub struct Remote {
+    stream: TcpStream,
+    reader: Option<BufReader<TcpStream>>,
+}
+
+impl Remote {
+    fn new(stream: TcpStream) -> io::Result<Remote> {
+        stream.set_nodelay(true)?;
+        let reader = BufReader::new(stream.try_clone()?);
+        Ok(Remote {
+            stream,
+            reader: Some(reader),
+        })
+    }
+
+    // Errors are left to the receiving side, which stops when the connection is gone
+    pub fn send(&self, message: &Message) {
+        writeln!(&self.stream, "{message}").ok();
+    }
+
+    fn receive(&mut self) -> io::Result<Message> {
+        let reader = self.reader.as_mut().expect("reader taken");
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        line.parse()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    // Incoming messages, until the connection is closed or something unreadable arrives.
+    // Can be taken only once.
+    pub fn messages(&mut self) -> impl Iterator<Item = Message> + Send {
+        let reader = self.reader.take().expect("reader taken");
+        reader
+            .lines()
+            .map_while(|line| line.ok()?.parse::<Message>().ok())
+    }
+
+    fn hello(&mut self) -> io::Result<()> {
+        self.send(&Message::Hello(PROTOCOL_VERSION));
+        match self.receive()? {
+            Message::Hello(PROTOCOL_VERSION) => Ok(()),
+            Message::Hello(version) => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("protocol version {version}, expected {PROTOCOL_VERSION}"),
+            )),
+            message => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("expected HELLO: {message}"),
+            )),
+        }
+    }
+}
+
+// Wait for one opponent. The server decides the seed and the options of the game.
+pub fn serve(port: u16, options: Options) -> io::Result<(Remote, Options)> {
+    let listener = TcpListener::bind(("0.0.0.0", port))?;
+    println!(
+        "waiting for the opponent on port {}...",
+        listener.local_addr()?.port()
+    );
+    let (stream, addr) = listener.accept()?;
+    println!("{addr} connected");
+
+    let mut remote = Remote::new(stream)?;
+    remote.hello()?;
+    let seed = options.seed.unwrap_or_else(rand::random);
+    remote.send(&Message::Options {
+        seed,
+        next_length: options.next_length,
+        hold: options.hold,
+    });
+    let options = Options {
+        seed: Some(seed),
+        ..options
+    };
+    Ok((remote, options))
+}
+
+// `addr` is host:port, or just the host for `default_port`
+pub fn open(addr: &str, default_port: u16) -> io::Result<TcpStream> {
+    match addr.to_socket_addrs() {
+        Ok(addrs) => TcpStream::connect(&*addrs.collect::<Vec<_>>()),
+        Err(_) => TcpStream::connect((addr, default_port)),
+    }
+}
+
+// Join a server, which decides the seed and the options of the game
+pub fn connect(addr: &str, options: Options) -> io::Result<(Remote, Options)> {
+    let mut remote = Remote::new(open(addr, DEFAULT_PORT)?)?;
+    remote.hello()?;
+    let Message::Options {
+        seed,
+        next_length,
+        hold,
+    } = remote.receive()?
+    else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "expected OPTIONS",
+        ));
+    };
+    let options = Options {
+        seed: Some(seed),
+        next_length,
+        hold,
+        ..options
+    };
+    Ok((remote, options))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mino::MINOS;
+
+    #[test]
+    fn test_message_round_trip() {
+        let mut field = Box::new(EMPTY_FIELD);
+        field[20][2] = block_kind::GARBAGE;
+        field[20][3] = block_kind::T;
+        field[19][11] = block_kind::I;
+
+        let messages = [
+            Message::Hello(PROTOCOL_VERSION),
+            Message::Options {
+                seed: u64::MAX,
+                next_length: 5,
+                hold: false,
+            },
+            Message::Ready,
+            Message::Hold,
+            Message::Lock {
+                mino: MINOS[6],
+                pos: Position { x: 5, y: 18 },
+                tspin: true,
+            },
+            Message::Field(field),
+            Message::Attack(4),
+            Message::Lose,
+        ];
+        for message in messages {
+            let line = message.to_string();
+            assert_eq!(line.parse::<Message>(), Ok(message), "{line}");
+        }
+        assert_eq!(
+            Message::Lock {
+                mino: MINOS[6],
+                pos: Position { x: 5, y: 18 },
+                tspin: false,
+            }
+            .to_string(),
+            "LOCK T 04e0 5 18 0"
+        );
+        assert!("LOCK X 04e0 5 18 0".parse::<Message>().is_err());
+        assert!("FIELD ...".parse::<Message>().is_err());
+        assert!("LOCK I 000f 12 0 0".parse::<Message>().is_err());
+        assert!("LOCK I 0000 5 5 0".parse::<Message>().is_err());
+        assert!("LOCK T e400 4 0 1".parse::<Message>().is_err());
+        assert!("LOCK G 04e0 5 18 0".parse::<Message>().is_err());
+        assert_eq!(
+            "ATTACK 18446744073709551615".parse::<Message>(),
+            Ok(Message::Attack(FIELD_HEIGHT))
+        );
+    }
+}
@@ -0,0 +1,155 @@
+use crate::block::{block_kind, BlockColor};
+use crate::fumen;
+use crate::game::*;
+use crate::mino::{MinoKind, MINO_KINDS};
+use crate::stats::PIECE_NAMES;
+use std::fs;
+
+pub const FIELD_ROWS: usize = 20; // rows below the spawn row
+pub const FIELD_COLUMNS: usize = 10;
+pub const LEFT: usize = 2; // column of the field where the text starts
+
+// `.` empty, `X` garbage, `IOSZJLT` minos, `#` the walls, which only the network sends
+pub(crate) fn block_char(block: BlockColor) -> char {
+    match block {
+        block_kind::NONE => '.',
+        block_kind::WALL => '#',
+        block_kind::I..=block_kind::T => PIECE_NAMES[block - block_kind::I].chars().next().unwrap(),
+        _ => 'X',
+    }
+}
+
+pub(crate) fn char_block(c: char) -> Option<BlockColor> {
+    match c {
+        '.' => Some(block_kind::NONE),
+        '#' => Some(block_kind::WALL),
+        'X' => Some(block_kind::GARBAGE),
+        _ => mino_kind(c).map(|kind| block_kind::I + kind as usize),
+    }
+}
+
+pub fn mino_kind(c: char) -> Option<MinoKind> {
+    PIECE_NAMES
+        .iter()
+        .position(|name| name.starts_with(c.to_ascii_uppercase()))
+        .map(|i| MINO_KINDS[i])
+}
+
+// Rows of 10 blocks from the top, the last row is the bottom of the field.
+// Empty rows at the top can be left out.
+pub fn parse_field(text: &str) -> Result<FieldSize, String> {
+    let rows: Vec<&str> = text
+        .lines()
+        .map(str::trim)
+        .filter(|row| !row.is_empty())
+        .collect();
+    if rows.len() > FIELD_ROWS {
+        return Err(format!("{} rows, at most {FIELD_ROWS}", rows.len()));
+    }
+    let mut field = EMPTY_FIELD;
+    let top = FIELD_ROWS + 1 - rows.len();
+    for (y, row) in rows.iter().enumerate() {
+        if row.chars().count() != FIELD_COLUMNS {
+            return Err(format!("{row:?}: expected {FIELD_COLUMNS} blocks"));
+        }
+        for (x, c) in row.chars().enumerate() {
+            field[top + y][LEFT + x] = char_block(c)
+                .filter(|&block| block != block_kind::WALL)
+                .ok_or_else(|| format!("{row:?}: unknown block {c:?}"))?;
+        }
+    }
+    Ok(field)
+}
+
+// From the highest row with a block down to the bottom, one line each
+pub fn format_field(field: &FieldSize) -> String {
+    let rows = &field[1..=FIELD_ROWS];
+    let top = rows
+        .iter()
+        .position(|row| {
+            row[LEFT..LEFT + FIELD_COLUMNS]
+                .iter()
+                .any(|&b| b != block_kind::NONE)
+        })
+        .unwrap_or(FIELD_ROWS);
+    rows[top..]
+        .iter()
+        .map(|row| {
+            row[LEFT..LEFT + FIELD_COLUMNS]
+                .iter()
+                .map(|&b| block_char(b))
+                .collect::<String>()
+                + "\n"
+        })
+        .collect()
+}
+
+// e.g. "TIOS"
+pub fn parse_minos(text: &str) -> Result<Vec<MinoKind>, String> {
+    text.trim()
+        .chars()
+        .map(|c| mino_kind(c).ok_or_else(|| format!("unknown mino {c:?}")))
+        .collect()
+}
+
+pub fn format_minos(minos: &[MinoKind]) -> String {
+    minos
+        .iter()
+        .map(|&kind| PIECE_NAMES[kind as usize])
+        .collect()
+}
+
+// The setup given on the command line. `field` is a fumen or a file of rows, `queue` starts with
+// the current mino, and both override the quiz comment of a fumen.
+pub fn load_setup(
+    field: Option<&str>,
+    queue: Option<&str>,
+    hold: Option<&str>,
+) -> Result<Option<Setup>, String> {
+    if field.is_none() && queue.is_none() && hold.is_none() {
+        return Ok(None);
+    }
+    let mut setup = match field {
+        Some(field) if fumen::is_fumen(field) => fumen::decode(field)?,
+        Some(path) => Setup {
+            field: parse_field(&fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?)?,
+            ..Default::default()
+        },
+        None => Setup::default(),
+    };
+    if let Some(queue) = queue {
+        setup.minos = parse_minos(queue)?;
+    }
+    if let Some(hold) = hold {
+        setup.hold = match parse_minos(hold)?[..] {
+            [] => None,
+            [kind] => Some(kind),
+            _ => return Err(format!("{hold:?}: hold one mino")),
+        };
+    }
+    Ok(Some(setup))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_field_round_trip() {
+        let text = "\
+            ....T.....\n\
+            ...TTT....\n\
+            IIII.XXXXX\n\
+            OO.SSZZJLL\n";
+        let field = parse_field(text).unwrap();
+        assert_eq!(field[FIELD_ROWS][LEFT], block_kind::O);
+        assert_eq!(field[FIELD_ROWS - 1][LEFT + 5], block_kind::GARBAGE);
+        assert_eq!(field[FIELD_ROWS - 3][LEFT + 4], block_kind::T);
+        assert_eq!(format_field(&field), text);
+
+        assert_eq!(format_field(&EMPTY_FIELD), "");
+        assert!(parse_field("....").is_err());
+        assert!(parse_field("....Q.....").is_err());
+        assert!(parse_field("....#.....").is_err());
+    }
+}
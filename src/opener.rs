@@ -0,0 +1,407 @@
+use crate::animation::Animation;
+use crate::block::block_kind;
+use crate::game::*;
+use crate::mino::{block_of, MinoKind, MINO_KINDS};
+use crate::notation::{parse_field, FIELD_COLUMNS, FIELD_ROWS, LEFT};
+use crate::play::{drill, key_input, Drill, Retry};
+use crate::stats::PIECE_NAMES;
+use getch_rs::Key;
+use std::collections::HashMap;
+
+// The first bag of each opener as `notation` rows: short name, name, field.
+// The DT cannon keeps its T for the double, so the T slot stays empty.
+pub const LIBRARY: [(&str, &str, &str); 3] = [
+    (
+        "tki",
+        "TKI",
+        "\
+        ZZ...S....\n\
+        JZZ..SS...\n\
+        JJJTTTSLOO\n\
+        IIIITLLLOO\n",
+    ),
+    (
+        "dt",
+        "DT cannon",
+        "\
+        S.........\n\
+        SS..ZZ.LLL\n\
+        JS...ZZLOO\n\
+        JJJ.IIIIOO\n",
+    ),
+    (
+        "pco",
+        "PCO",
+        "\
+        ....LLLJJJ\n\
+        ....LZOOSJ\n\
+        ..T.ZZOOSS\n\
+        .TTTZIIIIS\n",
+    ),
+];
+
+// Where each mino of an opener goes, the cells row by row as `mino_cells` gives them
+#[derive(Clone)]
+pub struct Opener {
+    pub name: &'static str,
+    placements: Vec<(MinoKind, Vec<(usize, usize)>)>,
+}
+
+impl Opener {
+    // the opener of the library with this short name
+    pub fn get(key: &str) -> Option<Opener> {
+        let (_, name, text) = LIBRARY.iter().find(|(k, ..)| *k == key)?;
+        Some(Opener::parse(name, text).unwrap())
+    }
+
+    // Each mino is used once, so its blocks are its cells
+    fn parse(name: &'static str, text: &str) -> Result<Opener, String> {
+        let field = parse_field(text)?;
+        let mut cells: HashMap<usize, Vec<(usize, usize)>> = HashMap::new();
+        for (y, row) in field.iter().enumerate().skip(1).take(FIELD_ROWS) {
+            for (x, &block) in row.iter().enumerate().skip(LEFT).take(FIELD_COLUMNS) {
+                match block {
+                    block_kind::NONE => {}
+                    block @ block_kind::I..=block_kind::T => {
+                        cells.entry(block).or_default().push((x, y))
+                    }
+                    _ => return Err(format!("{name}: only minos can be in an opener")),
+                }
+            }
+        }
+        let mut placements = vec![];
+        for (block, cells) in cells {
+            let kind = MINO_KINDS[block - block_kind::I];
+            if cells.len() != 4 {
+                return Err(format!(
+                    "{name}: {} blocks of {}",
+                    cells.len(),
+                    PIECE_NAMES[kind as usize]
+                ));
+            }
+            placements.push((kind, cells));
+        }
+        placements.sort_by_key(|&(kind, _)| kind as usize);
+        Ok(Opener { name, placements })
+    }
+
+    // the same opener from the other side, with S and Z, J and L swapped
+    fn mirror(&self) -> Opener {
+        let placements = self
+            .placements
+            .iter()
+            .map(|(kind, cells)| {
+                let kind = match kind {
+                    MinoKind::S => MinoKind::Z,
+                    MinoKind::Z => MinoKind::S,
+                    MinoKind::J => MinoKind::L,
+                    MinoKind::L => MinoKind::J,
+                    kind => *kind,
+                };
+                let mut cells: Vec<_> = cells
+                    .iter()
+                    .map(|&(x, y)| (2 * LEFT + FIELD_COLUMNS - 1 - x, y))
+                    .collect();
+                cells.sort_by_key(|&(x, y)| (y, x));
+                (kind, cells)
+            })
+            .collect();
+        Opener {
+            name: self.name,
+            placements,
+        }
+    }
+
+    // the cells still to be filled
+    fn cells(&self) -> Vec<(usize, usize)> {
+        self.placements
+            .iter()
+            .flat_map(|(_, cells)| cells.iter().copied())
+            .collect()
+    }
+}
+
+// The minos placed so far, checked against both sides of the opener until one of them is chosen
+#[derive(Clone)]
+struct Progress {
+    sides: Vec<Opener>,
+    total: usize,
+}
+
+impl Progress {
+    fn new(opener: &Opener) -> Progress {
+        Progress {
+            sides: vec![opener.clone(), opener.mirror()],
+            total: opener.placements.len(),
+        }
+    }
+
+    fn is_complete(&self) -> bool {
+        self.sides[0].placements.is_empty()
+    }
+
+    fn placed(&self) -> usize {
+        self.total - self.sides[0].placements.len()
+    }
+
+    // The mino locked on `cells` and the `rows` it cleared, Err when it is not the opener
+    fn lock(
+        &mut self,
+        kind: MinoKind,
+        cells: &[(usize, usize)],
+        rows: &[usize],
+    ) -> Result<(), String> {
+        let name = PIECE_NAMES[kind as usize];
+        let sides: Vec<Opener> = self
+            .sides
+            .iter()
+            .filter(|side| {
+                side.placements
+                    .iter()
+                    .any(|(k, c)| *k == kind && c == cells)
+            })
+            .cloned()
+            .collect();
+        if sides.is_empty() {
+            return Err(
+                if self.sides[0].placements.iter().any(|(k, _)| *k == kind) {
+                    format!("WRONG PLACE for the {name}")
+                } else {
+                    format!("NO {name} in this opener, hold it")
+                },
+            );
+        }
+        self.sides = sides;
+        for side in self.sides.iter_mut() {
+            side.placements.retain(|(k, _)| *k != kind);
+            // the blocks above the cleared rows fall
+            for (_, cells) in side.placements.iter_mut() {
+                for (_, y) in cells.iter_mut() {
+                    *y += rows.iter().filter(|&&row| row > *y).count();
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// The trainer shows where the minos of the opener go and takes back the ones placed elsewhere
+struct Trainer {
+    opener: Opener,
+    options: Options,
+    retry: Retry,
+    progress: Progress,
+    anim: Animation,
+    over: bool,
+    tries: usize,
+    completed: usize,
+    message: String,
+}
+
+impl Trainer {
+    fn new(opener: Opener, options: Options, animation: bool) -> Trainer {
+        Trainer {
+            progress: Progress::new(&opener),
+            opener,
+            options,
+            retry: Retry::new(Game::with_options(options)),
+            anim: Animation::new(animation),
+            over: false,
+            tries: 1,
+            completed: 0,
+            message: String::new(),
+        }
+    }
+
+    fn restart(&mut self) {
+        self.retry = Retry::new(Game::with_options(self.options));
+        self.progress = Progress::new(&self.opener);
+        self.over = false;
+        self.tries += 1;
+        self.message.clear();
+    }
+
+    fn input(&mut self, input: Input) {
+        if self.over {
+            return;
+        }
+        self.over = handle_input(&mut self.retry.game, input).is_err();
+        if input == Input::Hold {
+            self.retry.keep();
+        }
+        self.update();
+    }
+
+    fn update(&mut self) {
+        let events = std::mem::take(&mut self.retry.game.events);
+        let locked = events.iter().find_map(|event| match event {
+            Event::Lock { mino, pos, .. } => Some((block_of(mino), mino_cells(mino, pos))),
+            _ => None,
+        });
+        let Some((block, cells)) = locked else {
+            self.anim.push(events);
+            return;
+        };
+        if !self.progress.is_complete() {
+            let rows = events
+                .iter()
+                .find_map(|event| match event {
+                    Event::LineClear { rows, .. } => Some(rows.clone()),
+                    _ => None,
+                })
+                .unwrap_or_default();
+            let kind = MINO_KINDS[block - block_kind::I];
+            if let Err(message) = self.progress.lock(kind, &cells, &rows) {
+                // the same mino again
+                self.message = message;
+                self.retry.retry();
+                self.over = false;
+                return;
+            }
+            self.message = if self.progress.is_complete() {
+                self.completed += 1;
+                "COMPLETE, r: again".into()
+            } else {
+                "OK".into()
+            };
+        }
+        if self.over {
+            self.message = "GAME OVER, r: again".into();
+        }
+        self.anim.push(events);
+        self.retry.keep();
+    }
+}
+
+impl Drill for Trainer {
+    fn tick(&mut self) -> bool {
+        let redraw = self.anim.is_active();
+        self.anim.tick();
+        redraw
+    }
+
+    fn key(&mut self, key: &Key) -> bool {
+        if *key == Key::Char('r') {
+            self.restart();
+            return true;
+        }
+        let Some(input) = key_input(key) else {
+            return false;
+        };
+        self.input(input);
+        true
+    }
+
+    fn draw(&self) {
+        self.anim.draw(&self.retry.game);
+        draw_overlay(
+            &self.retry.game,
+            &self.progress.sides[0].cells(),
+            block_kind::TARGET,
+            0,
+        );
+        print!(
+            "\x1b[24;1H{}  {}/{}  {}\x1b[K",
+            self.opener.name,
+            self.progress.placed(),
+            self.progress.total,
+            self.message
+        );
+        let mirror = if self.progress.sides.len() > 1 {
+            ", or mirrored"
+        } else {
+            ""
+        };
+        print!("\x1b[25;1Hplace the minos on <>{mirror}  c: hold  r: restart  q: quit\x1b[K");
+        println!();
+    }
+
+    fn result(&self) -> String {
+        format!(
+            "{}: {}/{} completed",
+            self.opener.name, self.completed, self.tries
+        )
+    }
+}
+
+pub fn trainer(opener: Opener, options: Options, animation: bool) -> ! {
+    let options = Options {
+        gravity: false,
+        hold: true,
+        ..options
+    };
+    drill(Trainer::new(opener, options, animation))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::finesse::{search, Move};
+
+    // Place the rest of the opener in some order, with soft drop and spins
+    fn complete(field: FieldSize, progress: Progress) -> bool {
+        if progress.is_complete() {
+            return true;
+        }
+        let moves = [
+            Input::Left,
+            Input::Right,
+            Input::RotateRight,
+            Input::RotateLeft,
+            Input::SoftDrop,
+        ]
+        .map(Move::Tap);
+        for (kind, cells) in &progress.sides[0].placements {
+            let setup = Setup {
+                field,
+                minos: vec![*kind],
+                hold: None,
+            };
+            let mut game = Game::with_setup(Options::default(), &setup).unwrap();
+            let Some(moves) = search(&game, &moves).remove(cells) else {
+                continue;
+            };
+            for step in moves {
+                step.apply(&mut game);
+            }
+            handle_input(&mut game, Input::HardDrop).unwrap();
+            let rows = game
+                .events
+                .iter()
+                .find_map(|event| match event {
+                    Event::LineClear { rows, .. } => Some(rows.clone()),
+                    _ => None,
+                })
+                .unwrap_or_default();
+            let mut progress = progress.clone();
+            progress.lock(*kind, cells, &rows).unwrap();
+            if complete(game.field, progress) {
+                return true;
+            }
+        }
+        false
+    }
+
+    #[test]
+    fn test_library() {
+        for (key, ..) in LIBRARY {
+            let opener = Opener::get(key).unwrap();
+            for side in [opener.clone(), opener.mirror()] {
+                let progress = Progress {
+                    total: side.placements.len(),
+                    sides: vec![side],
+                };
+                assert!(complete(EMPTY_FIELD, progress), "{key}");
+            }
+        }
+
+        let tki = Opener::get("tki").unwrap();
+        let mut progress = Progress::new(&tki);
+        let i = &tki.mirror().placements[0];
+        assert_eq!(i.0, MinoKind::I);
+        assert!(progress.lock(MinoKind::O, &i.1, &[]).is_err());
+        progress.lock(MinoKind::I, &i.1, &[]).unwrap();
+        assert_eq!(progress.sides.len(), 1);
+        assert_eq!(progress.placed(), 1);
+    }
+}
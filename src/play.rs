@@ -1,122 +1,828 @@
-use crate::ai::eval;
+use crate::ai::{eval, Advice, DEFAULT_WEIGHT};
+use crate::animation::Animation;
+use crate::block::block_kind;
+use crate::finesse::Finesse;
 use crate::game::*;
+use crate::mino::MinoShape;
+use crate::records::{
+    add_score, format_score, load_best, now, player_name, rank, save_best, Record, Score,
+};
+use crate::replay::Replay;
+use crate::save::{Save, SAVE_VERSION};
+use crate::spectate::publish;
+use crate::stats::format_time;
+use crate::terminal;
 use getch_rs::{Getch, Key};
+use serde::{Deserialize, Serialize};
 use std::sync::{Arc, Mutex};
-use std::{thread, time};
+use std::{io, thread, time};
+
+pub const FRAME: time::Duration = time::Duration::from_nanos(1_000_000_000 / FPS);
+pub const COUNTDOWN_FRAMES: u64 = 3 * FPS;
+
+// Paces a frame loop by the clock: each frame is due a `FRAME` after the previous one was due, so
+// the time lost oversleeping is made up and `game.frame` keeps up with the wall clock
+pub struct Frames {
+    next: time::Instant,
+}
+
+impl Frames {
+    pub fn new() -> Frames {
+        Frames {
+            next: time::Instant::now(),
+        }
+    }
+
+    pub fn wait(&mut self) {
+        self.next += FRAME;
+        let now = time::Instant::now();
+        if self.next > now {
+            thread::sleep(self.next - now);
+        } else if now - self.next > time::Duration::from_secs(1) {
+            // far behind, e.g. after the process was stopped, start again from now
+            self.next = now;
+        }
+    }
+}
+
+// Game modes played with `normal`, the goal of the game is in `Options`
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    Normal,
+    Sprint,
+    Ultra,
+    Marathon,
+    Dig,
+    Practice,
+}
+
+impl Mode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mode::Normal => "normal",
+            Mode::Sprint => "sprint",
+            Mode::Ultra => "ultra",
+            Mode::Marathon => "marathon",
+            Mode::Dig => "dig",
+            Mode::Practice => "practice",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Menu,
+    Countdown(u64), // frames left until the game (re)starts
+    Playing,
+    Paused,
+    Over,
+}
+
+// Game with its frontend state
+struct Play {
+    mode: Mode,
+    setup: Option<Setup>, // position every game starts from, instead of the empty field
+    game: Game,
+    anim: Animation,
+    state: State,
+    best: Option<Record>, // personal best before this game
+    new_best: bool,
+    // the game and the finesse faults at the start and right after each lock, for undo in practice
+    history: Vec<(Game, usize)>,
+    inputs: Vec<(u64, Input)>, // for the replay
+    finesse: Finesse,
+    spawned: Game, // the current mino just appeared
+    hint: bool,
+    coach: Option<Coach>,
+    minos: u64, // minos followed so far, to tell whether an advice is still for the current one
+    advice: Option<Advice>, // for the current mino, while the hint or the coach is on
+    replay_saved: bool,
+    notice: Option<String>,  // shown while paused, e.g. the result of saving
+    scores: Vec<Score>,      // high score table of the finished game
+    position: Option<usize>, // of the finished game in `scores`
+}
+
+// How the placements of the player compare with the picks of the AI
+#[derive(Default)]
+struct Coach {
+    placed: usize,
+    matched: usize, // placed where the AI would have
+    loss: f64,      // AI score lost over the game
+    last: f64,
+}
+
+impl Coach {
+    fn judge(&mut self, advice: &Advice, mino: &MinoShape, pos: &Position) {
+        self.placed += 1;
+        if mino_cells(mino, pos) == advice.cells {
+            self.matched += 1;
+        }
+        self.last = advice.loss(mino, pos, &DEFAULT_WEIGHT);
+        self.loss += self.last;
+    }
+}
+
+impl Play {
+    fn new(mode: Mode, options: Options, setup: Option<Setup>, animation: bool) -> Play {
+        let game = start(options, setup.as_ref());
+        let mut finesse = Finesse::default();
+        finesse.start(&game);
+        Play {
+            finesse,
+            spawned: game.clone(),
+            hint: false,
+            coach: None,
+            minos: 0,
+            advice: None,
+            mode,
+            setup,
+            history: vec![(game.clone(), 0)],
+            game,
+            anim: Animation::new(animation),
+            state: State::Menu,
+            best: None,
+            new_best: false,
+            inputs: vec![],
+            replay_saved: false,
+            notice: None,
+            scores: vec![],
+            position: None,
+        }
+    }
+
+    // a saved game, paused so that the player can get ready
+    fn resumed(save: Save, animation: bool) -> Play {
+        let mut play = Play::new(save.mode, save.game.options, None, animation);
+        play.load(save);
+        play
+    }
+
+    fn load(&mut self, save: Save) {
+        self.mode = save.mode;
+        self.game = save.game;
+        self.inputs = save.inputs;
+        self.setup = save.setup;
+        self.best = None;
+        self.new_best = false;
+        self.scores.clear();
+        self.position = None;
+        if self.coach.is_some() {
+            self.coach = Some(Coach::default());
+        }
+        self.history = vec![(self.game.clone(), 0)];
+        self.finesse = Finesse::default();
+        self.new_mino();
+        self.replay_saved = false;
+        self.anim.clear();
+        self.state = State::Paused;
+        self.notice = Some("loaded".into());
+    }
+
+    // suspend the game into the save of this mode
+    fn save(&mut self) {
+        let save = Save {
+            version: SAVE_VERSION,
+            mode: self.mode,
+            game: self.game.clone(),
+            inputs: self.inputs.clone(),
+            setup: self.setup.clone(),
+        };
+        let result = Save::path(self.mode)
+            .ok_or(io::ErrorKind::NotFound.into())
+            .and_then(|path| save.save(&path));
+        self.state = State::Paused;
+        self.notice = Some(match result {
+            Ok(()) => "saved".into(),
+            Err(e) => format!("save failed: {}", e.kind()),
+        });
+    }
+
+    fn load_saved(&mut self) {
+        let result = Save::path(self.mode)
+            .ok_or(io::ErrorKind::NotFound.into())
+            .and_then(|path| Save::load(&path));
+        match result {
+            Ok(save) => self.load(save),
+            Err(e) => {
+                self.state = State::Paused;
+                self.notice = Some(match e.kind() {
+                    io::ErrorKind::NotFound => "no save".into(),
+                    kind => format!("load failed: {kind}"),
+                });
+            }
+        }
+    }
+
+    // fresh game with the same settings
+    fn restart(&mut self) {
+        self.game = start(self.game.options, self.setup.as_ref());
+        self.anim.clear();
+        self.history = vec![(self.game.clone(), 0)];
+        self.finesse = Finesse::default();
+        if self.coach.is_some() {
+            self.coach = Some(Coach::default());
+        }
+        self.new_mino();
+        self.inputs.clear();
+        self.replay_saved = false;
+        self.scores.clear();
+        self.position = None;
+        self.state = match self.mode {
+            Mode::Practice => State::Playing,
+            _ => State::Countdown(COUNTDOWN_FRAMES),
+        };
+    }
+
+    // back to where the last locked mino appeared, or the current one when nothing is locked yet
+    fn undo(&mut self) {
+        if self.history.len() > 1 {
+            self.history.pop();
+        }
+        (self.game, self.finesse.faults) = self.history[self.history.len() - 1].clone();
+        self.new_mino();
+        self.anim.clear();
+        self.state = State::Playing;
+    }
+
+    // a mino to follow after a lock, a hold or a restart
+    fn new_mino(&mut self) {
+        self.spawned = self.game.clone();
+        self.finesse.start(&self.spawned);
+        self.minos += 1;
+        self.advice = None;
+    }
+
+    // the current mino for the AI to pick a place for, when the hint or the coach wants one
+    fn advice_wanted(&self) -> Option<(u64, Game)> {
+        let wanted = (self.hint || self.coach.is_some()) && self.advice.is_none();
+        wanted.then(|| (self.minos, self.spawned.clone()))
+    }
+
+    // keep the advice unless the mino was placed meanwhile, true when the hint has to be drawn
+    fn set_advice(&mut self, minos: u64, advice: Advice) -> bool {
+        if minos != self.minos {
+            return false;
+        }
+        self.advice = Some(advice);
+        self.hint && self.state == State::Playing
+    }
+
+    // hand the engine events over to the animation after the game has been updated
+    fn update(&mut self, result: Result<(), ()>) {
+        if goal_reached(&self.game) {
+            self.state = State::Over;
+            self.finish();
+        } else if result.is_err() {
+            self.state = State::Over;
+        }
+        if self.state == State::Over {
+            self.save_replay();
+            self.record_score();
+        }
+        let events = std::mem::take(&mut self.game.events);
+        for event in &events {
+            if let Event::Lock { mino, pos, .. } = event {
+                self.finesse.lock(mino, pos);
+                // a mino placed before the AI picked a place for it is not judged
+                if let (Some(coach), Some(advice)) = (&mut self.coach, &self.advice) {
+                    coach.judge(advice, mino, pos);
+                }
+                self.new_mino();
+            }
+        }
+        if self.mode == Mode::Practice && events.iter().any(|e| matches!(e, Event::Lock { .. })) {
+            self.history.push((self.game.clone(), self.finesse.faults));
+        }
+        self.anim.push(events);
+    }
+
+    // compare the finished game with the personal best and keep the better one
+    fn finish(&mut self) {
+        let (Mode::Sprint, Goal::Lines(lines), None) =
+            (self.mode, self.game.options.goal, &self.setup)
+        else {
+            return;
+        };
+        let name = sprint_name(lines, &self.game.options);
+        let record = Record {
+            frame: self.game.frame,
+            pieces: self.game.stats.pieces,
+        };
+        self.best = load_best(&name);
+        self.new_best = self.best.is_none_or(|best| record.frame < best.frame);
+        if self.new_best {
+            save_best(&name, &record).ok();
+        }
+    }
+
+    // The high score table of the game, only finished games go into the tables ranked by time.
+    // Games from a setup are not ranked.
+    fn board(&self) -> Option<String> {
+        if self.setup.is_some() {
+            return None;
+        }
+        let finished = goal_reached(&self.game);
+        let board = match (self.mode, self.game.options.goal) {
+            (Mode::Normal, _) => "normal".to_string(),
+            (Mode::Sprint, Goal::Lines(lines)) if finished => {
+                sprint_name(lines, &self.game.options)
+            }
+            (Mode::Ultra, Goal::Time(time)) => format!("ultra-{}", time / FPS),
+            (Mode::Marathon, Goal::Level(level)) => format!("marathon-level-{level}"),
+            (Mode::Marathon, Goal::Lines(lines)) => format!("marathon-lines-{lines}"),
+            (Mode::Dig, Goal::Garbage(lines)) if finished => format!("dig-{lines}"),
+            _ => return None,
+        };
+        Some(board)
+    }
+
+    fn record_score(&mut self) {
+        let Some(board) = self.board() else {
+            return;
+        };
+        let score = Score {
+            name: player_name(),
+            score: self.game.score,
+            lines: self.game.total_line,
+            frame: self.game.frame,
+            date: now(),
+            seed: self.game.seed,
+        };
+        (self.scores, self.position) = add_score(&board, rank(&board), score);
+    }
+
+    // the high scores on the right of the result
+    fn draw_scores(&self) {
+        let Some(board) = self.board() else {
+            return;
+        };
+        let rank = rank(&board);
+        print!("\x1b[2;30HHIGH SCORES {board}");
+        for (i, score) in self.scores.iter().enumerate() {
+            let line = format_score(i, rank, score);
+            if self.position == Some(i) {
+                print!("\x1b[{};30H\x1b[1m{line} <\x1b[0m", i + 4);
+            } else {
+                print!("\x1b[{};30H{line}", i + 4);
+            }
+        }
+        println!();
+    }
+
+    // Undo can't be replayed, so practice games are not saved
+    fn save_replay(&mut self) {
+        if self.mode == Mode::Practice || self.game.frame == 0 {
+            return;
+        }
+        let inputs = std::mem::take(&mut self.inputs);
+        let mut replay = Replay::new(self.mode, &self.game, None, inputs);
+        replay.setup = self.setup.clone();
+        self.replay_saved = replay.save().is_ok();
+    }
+
+    fn title(&self) -> String {
+        match (self.mode, self.game.options.goal) {
+            (Mode::Sprint, Goal::Lines(lines)) => format!("SPRINT {lines} LINES"),
+            (Mode::Ultra, Goal::Time(time)) => {
+                format!("ULTRA {}:{:02}", time / FPS / 60, time / FPS % 60)
+            }
+            (Mode::Marathon, Goal::Level(level)) => format!("MARATHON TO LEVEL {level}"),
+            (Mode::Marathon, Goal::Lines(lines)) => format!("MARATHON {lines} LINES"),
+            (Mode::Dig, Goal::Garbage(lines)) => format!("DIG {lines} LINES"),
+            (Mode::Practice, _) => "PRACTICE".to_string(),
+            _ => "TETRIS".to_string(),
+        }
+    }
+
+    fn result(&self) -> Vec<String> {
+        let finished = goal_reached(&self.game);
+        let mut lines = match self.mode {
+            Mode::Sprint if finished => {
+                let mut lines = vec![
+                    "CLEAR!".to_string(),
+                    String::new(),
+                    format!("time: {}", format_time(self.game.frame)),
+                    format!("pieces: {}", self.game.stats.pieces),
+                ];
+                if let Some(best) = self.best {
+                    lines.push(format!("best: {}", format_time(best.frame)));
+                }
+                if self.new_best {
+                    lines.push("NEW BEST!".to_string());
+                }
+                lines
+            }
+            Mode::Dig if finished => vec![
+                "CLEAR!".to_string(),
+                String::new(),
+                format!("time: {}", format_time(self.game.frame)),
+                format!("pieces: {}", self.game.stats.pieces),
+            ],
+            Mode::Ultra if finished => vec![
+                "TIME UP!".to_string(),
+                String::new(),
+                format!("score: {}", self.game.score),
+                format!("lines: {}", self.game.total_line),
+                format!("pieces: {}", self.game.stats.pieces),
+            ],
+            Mode::Marathon => vec![
+                if finished { "COMPLETE!" } else { "GAME OVER" }.to_string(),
+                String::new(),
+                format!("score: {}", self.game.score),
+                format!("lines: {}", self.game.total_line),
+                format!("level: {}", level(&self.game)),
+                format!("time: {}", format_time(self.game.frame)),
+            ],
+            _ => vec![
+                "GAME OVER".to_string(),
+                String::new(),
+                format!("score: {}", self.game.score),
+                format!("lines: {}", self.game.total_line),
+            ],
+        };
+        lines.push(format!("finesse faults: {}", self.finesse.faults));
+        if let Some(coach) = &self.coach {
+            lines.push(format!("AI picks: {}/{}", coach.matched, coach.placed));
+            lines.push(format!("AI score lost: {:.1}", coach.loss));
+        }
+        lines.push(format!("seed: {}", self.game.seed));
+        if self.replay_saved {
+            lines.push("replay saved".into());
+        }
+        lines.extend([String::new(), "r: play again".into()]);
+        if self.mode == Mode::Practice {
+            lines.push("u: undo".into());
+        }
+        lines.push("q: quit".into());
+        lines
+    }
+
+    fn draw(&self) {
+        // spectators see what the player sees, so not the field hidden by the pause
+        if matches!(
+            self.state,
+            State::Countdown(_) | State::Playing | State::Over
+        ) {
+            publish(&self.game);
+        }
+        match self.state {
+            State::Menu => {
+                let title = self.title();
+                let mut lines = vec![
+                    title.as_str(),
+                    "",
+                    "enter: start",
+                    "q: quit",
+                    "",
+                    "<- ->: move",
+                    "down: soft drop",
+                    "space: hard drop",
+                    "up z: rotate",
+                    "c: hold",
+                    "p: pause",
+                    "r: restart",
+                    "l: load",
+                    "h: hint  k: coach",
+                ];
+                if self.mode == Mode::Practice {
+                    lines.push("u: undo");
+                }
+                draw_message(&lines);
+            }
+            State::Countdown(frames) => {
+                self.anim.draw(&self.game);
+                let count = frames.div_ceil(FPS);
+                print!("\x1b[11;11H\x1b[1m {count} \x1b[0m");
+                println!();
+            }
+            State::Playing => {
+                self.anim.draw(&self.game);
+                if let (true, Some(advice)) = (self.hint, &self.advice) {
+                    draw_overlay(&self.game, &advice.cells, block_kind::HINT, 0);
+                }
+                print!("\x1b[15;50HFAULTS {}\x1b[K", self.finesse.faults);
+                match &self.coach {
+                    Some(coach) => print!(
+                        "\x1b[24;1HCOACH  AI picks {}/{}  score lost {:.1}  last {:.1}\x1b[K",
+                        coach.matched, coach.placed, coach.loss, coach.last
+                    ),
+                    None => print!("\x1b[24;1H\x1b[K"),
+                }
+                match &self.advice {
+                    Some(advice) if self.hint && advice.hold => {
+                        print!("\x1b[25;1HHINT  hold first\x1b[K")
+                    }
+                    _ => print!("\x1b[25;1H\x1b[K"),
+                }
+                println!();
+            }
+            // the field is hidden so that nobody can think while the game is paused
+            State::Paused => draw_message(&[
+                "PAUSE",
+                "",
+                "p: resume",
+                "s: save",
+                "l: load",
+                "r: restart",
+                "q: quit",
+                "",
+                self.notice.as_deref().unwrap_or_default(),
+            ]),
+            State::Over if self.anim.is_active() => self.anim.draw(&self.game),
+            State::Over => {
+                let result = self.result();
+                draw_message(&result.iter().map(String::as_str).collect::<Vec<_>>());
+                self.draw_scores();
+            }
+        }
+    }
+}
+
+// The personal best and the table of a sprint. Runs with another number of next minos or without
+// hold are apart from the others.
+fn sprint_name(lines: usize, options: &Options) -> String {
+    let mut name = format!("sprint-{lines}");
+    if options.next_length != NEXT_LENGTH {
+        name += &format!("-next{}", options.next_length);
+    }
+    if !options.hold {
+        name += "-nohold";
+    }
+    name
+}
+
+pub fn key_input(key: &Key) -> Option<Input> {
+    match key {
+        Key::Left => Some(Input::Left),
+        Key::Right => Some(Input::Right),
+        Key::Down => Some(Input::SoftDrop),
+        Key::Char(' ') => Some(Input::HardDrop),
+        Key::Up => Some(Input::RotateRight),
+        Key::Char('z') => Some(Input::RotateLeft),
+        Key::Char('c') => Some(Input::Hold),
+        _ => None,
+    }
+}
+
+// empty well with some text in it
+pub fn draw_message(lines: &[&str]) {
+    println!("\x1b[2J");
+    draw_field(&EMPTY_FIELD, 0);
+    for (i, line) in lines.iter().enumerate() {
+        print!("\x1b[{};5H{line}", i + 6);
+    }
+    println!();
+}
+
+// A mode with its own rules, played with the keys of `normal` on a game without gravity
+pub trait Drill: Send + 'static {
+    // one frame, true when it has to be drawn again
+    fn tick(&mut self) -> bool;
+    // any key but q, true when it has to be drawn again
+    fn key(&mut self, key: &Key) -> bool;
+    fn draw(&self);
+    // printed when the player quits
+    fn result(&self) -> String;
+}
+
+// Run the drill until q
+pub fn drill(drill: impl Drill) -> ! {
+    let drill = Arc::new(Mutex::new(drill));
+    drill.lock().unwrap().draw();
+
+    {
+        let drill = Arc::clone(&drill);
+
+        let _ = thread::spawn(move || {
+            let mut frames = Frames::new();
+            loop {
+                frames.wait();
+                let mut drill = drill.lock().unwrap();
+                if drill.tick() {
+                    drill.draw();
+                }
+            }
+        });
+    }
+
+    let g = Getch::new();
+    loop {
+        let key = g.getch();
+        let mut drill = drill.lock().unwrap();
+        match key {
+            Ok(Key::Char('q') | Key::Ctrl('c')) => {
+                let result = drill.result();
+                terminal::restore();
+                println!("{result}");
+                quit();
+            }
+            Ok(key) if drill.key(&key) => drill.draw(),
+            _ => {}
+        }
+    }
+}
+
+// The game of a trainer, with the game when its current mino appeared to take the mino back
+pub struct Retry {
+    pub game: Game,
+    start: Game,
+}
+
+impl Retry {
+    pub fn new(game: Game) -> Retry {
+        Retry {
+            start: game.clone(),
+            game,
+        }
+    }
+
+    // the current mino again
+    pub fn retry(&mut self) {
+        self.game = self.start.clone();
+    }
+
+    // a new mino appeared, after a lock or a hold
+    pub fn keep(&mut self) {
+        self.start = self.game.clone();
+    }
+}
+
+// The setup was checked to fit on the command line
+fn start(options: Options, setup: Option<&Setup>) -> Game {
+    setup
+        .and_then(|setup| Game::with_setup(options, setup).ok())
+        .unwrap_or_else(|| Game::with_options(options))
+}
 
 // Normal play
-pub fn normal() -> ! {
-    let game = Arc::new(Mutex::new(Game::new()));
+pub fn normal(mode: Mode, options: Options, setup: Option<Setup>, animation: bool) -> ! {
+    run(Play::new(mode, options, setup, animation))
+}
 
-    // clear console screen
-    println!("\x1b[2J\x1b[H\x1b[?25l");
-    draw(&game.lock().unwrap());
+// Go on with a saved game
+pub fn resume(save: Save, animation: bool) -> ! {
+    run(Play::resumed(save, animation))
+}
+
+fn run(play: Play) -> ! {
+    let play = Arc::new(Mutex::new(play));
+    play.lock().unwrap().draw();
 
     {
-        let game = Arc::clone(&game);
+        let play = Arc::clone(&play);
 
-        let _ = thread::spawn(move || loop {
-            let sleep_msec =
-                match 1000u64.saturating_sub((game.lock().unwrap().total_line as u64 / 10) * 100) {
-                    0 => 100,
-                    msec => msec,
+        let _ = thread::spawn(move || {
+            let mut frames = Frames::new();
+            loop {
+                frames.wait();
+                let mut play = play.lock().unwrap();
+                let mut redraw = play.anim.is_active();
+                match play.state {
+                    State::Countdown(0) => {
+                        play.state = State::Playing;
+                        redraw = true;
+                    }
+                    State::Countdown(frames) => {
+                        play.state = State::Countdown(frames - 1);
+                        redraw |= frames.is_multiple_of(FPS);
+                    }
+                    State::Playing => {
+                        let result = tick(&mut play.game);
+                        // keep the timer in the HUD running
+                        redraw |= result != Ok(false) || play.game.frame.is_multiple_of(FPS / 10);
+                        play.update(result.map(|_| ()));
+                        redraw |= play.state != State::Playing;
+                    }
+                    State::Menu | State::Paused | State::Over => (),
+                }
+                play.anim.tick();
+                if redraw {
+                    play.draw();
+                }
+            }
+        });
+    }
+
+    {
+        let play = Arc::clone(&play);
+
+        // the AI thinks for long, so away from the lock which the game needs every frame
+        let _ = thread::spawn(move || {
+            let mut frames = Frames::new();
+            loop {
+                frames.wait();
+                let Some((minos, game)) = play.lock().unwrap().advice_wanted() else {
+                    continue;
                 };
-            thread::sleep(time::Duration::from_millis(sleep_msec));
-            let mut game = game.lock().unwrap();
-            let new_pos = Position {
-                x: game.pos.x,
-                y: game.pos.y + 1,
-            };
-            if !is_collision(&game.field, &new_pos, &game.mino) {
-                game.pos = new_pos;
-            } else if landing(&mut game).is_err() {
-                gameover(&game);
-            }
-            draw(&game);
+                let advice = Advice::new(&game, &DEFAULT_WEIGHT);
+                let mut play = play.lock().unwrap();
+                if play.set_advice(minos, advice) {
+                    play.draw();
+                }
+            }
         });
     }
 
     let g = Getch::new();
     loop {
-        match g.getch() {
-            Ok(Key::Left) => {
-                let mut game = game.lock().unwrap();
-                let new_pos = Position {
-                    x: game.pos.x.checked_sub(1).unwrap_or(game.pos.x),
-                    y: game.pos.y,
-                };
-                move_mino(&mut game, new_pos);
-                draw(&game);
+        let key = g.getch();
+        let mut play = play.lock().unwrap();
+        if let Ok(Key::Char('q') | Key::Ctrl('c')) = key {
+            // quitting in the middle of a game ends it too
+            if play.state != State::Over {
+                play.save_replay();
             }
-            Ok(Key::Down) => {
-                let mut game = game.lock().unwrap();
-                let new_pos = Position {
-                    x: game.pos.x,
-                    y: game.pos.y + 1,
-                };
-                move_mino(&mut game, new_pos);
-                draw(&game);
+            quit();
+        }
+
+        match (play.state, key) {
+            (State::Menu, Ok(Key::Char('\n' | '\r' | ' '))) => {
+                play.state = State::Countdown(COUNTDOWN_FRAMES);
             }
-            Ok(Key::Right) => {
-                let mut game = game.lock().unwrap();
-                let new_pos = Position {
-                    x: game.pos.x + 1,
-                    y: game.pos.y,
-                };
-                move_mino(&mut game, new_pos);
-                draw(&game);
+            (State::Playing, Ok(Key::Char('p') | Key::Esc)) => {
+                play.state = State::Paused;
             }
-            Ok(Key::Char(' ')) => {
-                let mut game = game.lock().unwrap();
-                hard_drop(&mut game);
-                landing(&mut game);
-                draw(&game);
+            (State::Paused, Ok(Key::Char('p') | Key::Esc)) => {
+                play.notice = None;
+                play.state = State::Countdown(COUNTDOWN_FRAMES);
             }
-            Ok(Key::Char('c')) => {
-                let mut game = game.lock().unwrap();
-                hold(&mut game);
-                draw(&game);
+            (State::Playing | State::Paused, Ok(Key::Char('s'))) => {
+                play.save();
             }
-            Ok(Key::Up) => {
-                let mut game = game.lock().unwrap();
-                rotate_right(&mut game);
-                draw(&game);
+            (State::Menu | State::Paused | State::Over, Ok(Key::Char('l'))) => {
+                play.load_saved();
             }
-            Ok(Key::Char('z')) => {
-                let mut game = game.lock().unwrap();
-                rotate_left(&mut game);
-                draw(&game);
+            (State::Playing | State::Paused | State::Over, Ok(Key::Char('r'))) => {
+                play.restart();
             }
-            Ok(Key::Char('q')) => {
-                quit();
+            (State::Playing | State::Over, Ok(Key::Char('u'))) if play.mode == Mode::Practice => {
+                play.undo();
             }
-
-            _ => (),
+            (State::Playing, Ok(Key::Char('h'))) => play.hint = !play.hint,
+            (State::Playing, Ok(Key::Char('k'))) => {
+                play.coach = match play.coach {
+                    Some(_) => None,
+                    None => Some(Coach::default()),
+                };
+            }
+            (State::Playing, Ok(key)) => {
+                let Some(input) = key_input(&key) else {
+                    continue;
+                };
+                let frame = play.game.frame;
+                play.inputs.push((frame, input));
+                let result = handle_input(&mut play.game, input);
+                let play = &mut *play;
+                play.finesse.input(input, &play.game);
+                if input == Input::Hold {
+                    play.new_mino();
+                }
+                play.update(result);
+            }
+            _ => continue,
         }
+        play.draw();
     }
 }
 
 // Auto play
-pub fn auto() -> ! {
-    let _ = thread::spawn(|| {
-        let mut game = Game::new();
-        println!("\x1b[2J\x1b[H\x1b[?25l"); // clear screen
-        draw(&game);
+pub fn auto(options: Options) -> ! {
+    let game = Arc::new(Mutex::new(Game::with_options(options)));
+    draw(&game.lock().unwrap());
 
-        loop {
-            let elite = eval(&game, &[100, 1, 10, 100]);
-            game = elite;
-            if landing(&mut game).is_err() {
-                gameover(&game);
+    {
+        let game = Arc::clone(&game);
+
+        let _ = thread::spawn(move || loop {
+            {
+                let mut game = game.lock().unwrap();
+                *game = eval(&game, &DEFAULT_WEIGHT);
+                if landing(&mut game).is_err() {
+                    save_auto_replay(&game);
+                    gameover(&game);
+                }
+                game.events.clear();
+                draw(&game);
+                publish(&game);
             }
-            draw(&game);
-        }
-    });
+            // let `q` take the lock between the minos
+            thread::sleep(time::Duration::from_millis(1));
+        });
+    }
 
     let g = Getch::new();
     loop {
-        if let Ok(Key::Char('q')) = g.getch() {
+        if let Ok(Key::Char('q') | Key::Ctrl('c')) = g.getch() {
+            save_auto_replay(&game.lock().unwrap());
             quit();
         }
     }
 }
+
+// the AI plays the same game again with the same weights, so only the end is needed
+fn save_auto_replay(game: &Game) {
+    Replay::new(Mode::Normal, game, Some(DEFAULT_WEIGHT), vec![])
+        .save()
+        .ok();
+}
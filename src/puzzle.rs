@@ -0,0 +1,494 @@
+use crate::animation::Animation;
+use crate::block::block_kind;
+use crate::fumen;
+use crate::game::*;
+use crate::mino::{block_of, MINO_KINDS};
+use crate::notation::{parse_field, parse_minos};
+use crate::play::{draw_message, drill, key_input, Drill};
+use getch_rs::Key;
+use rand::{seq::SliceRandom, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::fs;
+use std::path::Path;
+
+const PC_MINOS: usize = 10;
+
+// What the player has to do with the queue
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Target {
+    Lines(usize),
+    PerfectClear,
+    TSpinDouble,
+    Survive(usize), // place this many minos without topping out
+}
+
+impl Target {
+    fn parse(text: &str) -> Result<Target, String> {
+        let count = |n: &str| n.parse::<usize>().map_err(|e| format!("{text:?}: {e}"));
+        match text.split_whitespace().collect::<Vec<_>>()[..] {
+            ["lines", n] => Ok(Target::Lines(count(n)?)),
+            ["pc"] => Ok(Target::PerfectClear),
+            ["tsd"] => Ok(Target::TSpinDouble),
+            ["survive", n] => Ok(Target::Survive(count(n)?)),
+            _ => Err(format!(
+                "unknown goal {text:?}, expected lines <n>, pc, tsd or survive <n>"
+            )),
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Target::Lines(lines) => format!("clear {lines} lines"),
+            Target::PerfectClear => "perfect clear".into(),
+            Target::TSpinDouble => "T-spin double".into(),
+            Target::Survive(pieces) => format!("place {pieces} minos"),
+        }
+    }
+}
+
+pub struct Puzzle {
+    pub name: String,
+    pub target: Target,
+    pub setup: Setup,
+}
+
+// Puzzles separated by `---` lines, each of them made of `key value` lines and the rows of the
+// field, see README.md
+pub fn parse(text: &str) -> Result<Vec<Puzzle>, String> {
+    let mut puzzles = vec![];
+    let mut lines = text.lines().enumerate().peekable();
+    while lines.peek().is_some() {
+        let mut name = None;
+        let mut target = None;
+        let mut queue = None;
+        let mut hold = None;
+        let mut setup = Setup::default();
+        let mut rows = String::new();
+        let number = puzzles.len() + 1;
+        for (i, line) in lines.by_ref() {
+            let line = line.trim();
+            if line == "---" {
+                break;
+            }
+            let error = |e: String| format!("line {}: {e}", i + 1);
+            match line.split_once(' ').unwrap_or((line, "")) {
+                ("", _) => {}
+                (comment, _) if comment.starts_with('#') => {}
+                ("name", value) => name = Some(value.trim().to_string()),
+                ("goal", value) => target = Some(Target::parse(value).map_err(error)?),
+                ("queue", value) => queue = Some(parse_minos(value).map_err(error)?),
+                ("hold", value) => {
+                    let minos = parse_minos(value).map_err(error)?;
+                    if minos.len() > 1 {
+                        return Err(error(format!("{value:?}: only one mino can be held")));
+                    }
+                    hold = Some(minos);
+                }
+                ("fumen", value) => setup = fumen::decode(value).map_err(error)?,
+                _ => rows += &format!("{line}\n"),
+            }
+        }
+        if !rows.is_empty() {
+            setup.field = parse_field(&rows).map_err(|e| format!("puzzle {number}: {e}"))?;
+        }
+        if let Some(queue) = queue {
+            setup.minos = queue;
+        }
+        if let Some(hold) = hold {
+            setup.hold = hold.first().copied();
+        }
+        let target = target.ok_or_else(|| format!("puzzle {number}: no goal"))?;
+        if setup.minos.is_empty() {
+            return Err(format!("puzzle {number}: no queue"));
+        }
+        let minos = setup.minos.len() + setup.hold.is_some() as usize;
+        if let Target::Survive(pieces) = target {
+            if pieces > minos {
+                return Err(format!(
+                    "puzzle {number}: {pieces} minos to place, only {minos} given"
+                ));
+            }
+        }
+        if Game::with_setup(Options::default(), &setup).is_err() {
+            return Err(format!("puzzle {number}: the first mino doesn't fit"));
+        }
+        puzzles.push(Puzzle {
+            name: name.unwrap_or_else(|| format!("puzzle {number}")),
+            target,
+            setup,
+        });
+    }
+    if puzzles.is_empty() {
+        return Err("no puzzle".into());
+    }
+    Ok(puzzles)
+}
+
+pub fn load(path: &Path) -> Result<Vec<Puzzle>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    parse(&text).map_err(|e| format!("{}: {e}", path.display()))
+}
+
+#[derive(Clone, PartialEq)]
+enum State {
+    Playing,
+    Solved,
+    Failed(&'static str),
+}
+
+// One try at a puzzle
+struct Attempt {
+    game: Game,
+    state: State,
+    allowed: [usize; 7], // minos of each kind in the queue and the hold
+    queued: usize,       // minos in the queue, the hold at the start left out
+    placed: [usize; 7],
+    next_length: usize, // of the options, the next minos shown never go past the queue
+    perfect: bool,
+    tsd: bool,
+}
+
+impl Attempt {
+    // every puzzle was checked to fit when it was parsed
+    fn new(puzzle: &Puzzle, options: Options) -> Attempt {
+        let game = Game::with_setup(options, &puzzle.setup).unwrap();
+        let mut allowed = [0; 7];
+        for &kind in puzzle.setup.minos.iter().chain(&puzzle.setup.hold) {
+            allowed[kind as usize] += 1;
+        }
+        let mut attempt = Attempt {
+            game,
+            state: State::Playing,
+            allowed,
+            queued: puzzle.setup.minos.len(),
+            placed: [0; 7],
+            next_length: options.next_length,
+            perfect: false,
+            tsd: false,
+        };
+        attempt.hide_random_minos();
+        attempt
+    }
+
+    // the random minos after the queue are not part of the puzzle
+    fn hide_random_minos(&mut self) {
+        let received: usize = self.game.stats.received.iter().sum();
+        self.game.options.next_length = self.next_length.min(self.queued.saturating_sub(received));
+    }
+
+    // check the engine events and hand them back for the animation
+    fn update(&mut self, target: Target, result: Result<(), ()>) -> Vec<Event> {
+        let events = std::mem::take(&mut self.game.events);
+        for event in &events {
+            match event {
+                Event::Lock { mino, .. } => {
+                    self.placed[block_of(mino) - block_kind::I] += 1;
+                }
+                Event::LineClear { rows, tspin, .. } => {
+                    self.tsd |= *tspin && rows.len() == 2;
+                }
+                Event::PerfectClear => self.perfect = true,
+                _ => {}
+            }
+        }
+        self.hide_random_minos();
+
+        let solved = match target {
+            Target::Lines(lines) => self.game.total_line >= lines,
+            Target::PerfectClear => self.perfect,
+            Target::TSpinDouble => self.tsd,
+            Target::Survive(pieces) => self.game.stats.pieces >= pieces,
+        };
+        self.state = if self.placed.iter().zip(self.allowed).any(|(&p, a)| p > a) {
+            State::Failed("that mino was not in the queue")
+        } else if solved {
+            State::Solved
+        } else if result.is_err() {
+            State::Failed("topped out")
+        } else if self.placed == self.allowed {
+            State::Failed("out of minos")
+        } else {
+            State::Playing
+        };
+        events
+    }
+}
+
+// A puzzle pack being played
+struct Pack {
+    puzzles: Vec<Puzzle>,
+    current: usize,
+    attempt: Attempt,
+    anim: Animation,
+    options: Options,
+    solved: Vec<bool>,
+    tries: usize,             // attempts which ended, retries included
+    clears: usize,            // of them, the solved ones
+    deal: Option<ChaCha8Rng>, // deals a new puzzle after the last one in PC practice
+}
+
+impl Pack {
+    fn new(
+        puzzles: Vec<Puzzle>,
+        deal: Option<ChaCha8Rng>,
+        options: Options,
+        animation: bool,
+    ) -> Pack {
+        let options = Options {
+            gravity: false,
+            ..options
+        };
+        let attempt = Attempt::new(&puzzles[0], options);
+        Pack {
+            solved: vec![false; puzzles.len()],
+            tries: 0,
+            clears: 0,
+            puzzles,
+            current: 0,
+            attempt,
+            anim: Animation::new(animation),
+            options,
+            deal,
+        }
+    }
+
+    fn start(&mut self, current: usize) {
+        if let Some(rng) = self.deal.as_mut().filter(|_| current == self.puzzles.len()) {
+            self.puzzles.push(deal(rng, current + 1));
+            self.solved.push(false);
+        }
+        self.current = current;
+        self.attempt = Attempt::new(&self.puzzles[current], self.options);
+        self.anim.clear();
+    }
+
+    fn update(&mut self, result: Result<(), ()>) {
+        let puzzle = &self.puzzles[self.current];
+        let events = self.attempt.update(puzzle.target, result);
+        // an attempt is only updated while it is played, so it ends once
+        if self.attempt.state != State::Playing {
+            self.tries += 1;
+        }
+        if self.attempt.state == State::Solved {
+            self.clears += 1;
+            self.solved[self.current] = true;
+        }
+        self.anim.push(events);
+    }
+
+    fn has_next(&self) -> bool {
+        self.deal.is_some() || self.current + 1 < self.puzzles.len()
+    }
+
+    fn summary(&self) -> String {
+        let solved = self.solved.iter().filter(|&&solved| solved).count();
+        if self.deal.is_none() {
+            return format!("puzzles solved: {solved}/{}", self.puzzles.len());
+        }
+        let rate = if self.tries == 0 {
+            0.0
+        } else {
+            self.clears as f64 / self.tries as f64 * 100.0
+        };
+        format!(
+            "perfect clears: {}/{} ({rate:.0}%)",
+            self.clears, self.tries
+        )
+    }
+}
+
+impl Drill for Pack {
+    fn tick(&mut self) -> bool {
+        let mut redraw = self.anim.is_active();
+        if self.attempt.state == State::Playing {
+            let result = tick(&mut self.attempt.game);
+            redraw |= result != Ok(false);
+            self.update(result.map(|_| ()));
+        }
+        self.anim.tick();
+        redraw
+    }
+
+    fn key(&mut self, key: &Key) -> bool {
+        match key {
+            Key::Char('r') => self.start(self.current),
+            Key::Char('n') if self.has_next() => self.start(self.current + 1),
+            key if self.attempt.state == State::Playing => {
+                let Some(input) = key_input(key) else {
+                    return false;
+                };
+                let result = handle_input(&mut self.attempt.game, input);
+                self.update(result);
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    fn draw(&self) {
+        let puzzle = &self.puzzles[self.current];
+        let next = match (self.has_next(), &self.deal) {
+            (false, _) => "",
+            (true, None) => "n: next puzzle",
+            (true, Some(_)) => "n: next queue",
+        };
+        match &self.attempt.state {
+            State::Playing => {
+                self.anim.draw(&self.attempt.game);
+                let title = match self.deal {
+                    Some(_) => format!("PC PRACTICE  {}", self.summary()),
+                    None => format!("PUZZLE {}/{}", self.current + 1, self.puzzles.len()),
+                };
+                print!(
+                    "\x1b[24;1H{title} {}  goal: {}\x1b[K",
+                    puzzle.name,
+                    puzzle.target.describe()
+                );
+                print!("\x1b[25;1Hr: retry  {next}  q: quit\x1b[K");
+                println!();
+            }
+            _ if self.anim.is_active() => self.anim.draw(&self.attempt.game),
+            state => {
+                let result = match state {
+                    State::Failed(reason) => format!("FAILED: {reason}"),
+                    _ => "SOLVED!".into(),
+                };
+                let summary = self.summary();
+                draw_message(&[
+                    &result,
+                    "",
+                    &puzzle.name,
+                    &summary,
+                    "",
+                    "r: retry",
+                    next,
+                    "q: quit",
+                ]);
+            }
+        }
+    }
+
+    fn result(&self) -> String {
+        self.summary()
+    }
+}
+
+// The first bag and 3 minos of the second, the 10 minos of a 4 line perfect clear
+fn deal(rng: &mut ChaCha8Rng, number: usize) -> Puzzle {
+    let mut bags = [MINO_KINDS, MINO_KINDS];
+    for bag in bags.iter_mut() {
+        bag.shuffle(rng);
+    }
+    Puzzle {
+        name: format!("#{number}"),
+        target: Target::PerfectClear,
+        setup: Setup {
+            minos: bags.concat()[..PC_MINOS].to_vec(),
+            ..Default::default()
+        },
+    }
+}
+
+// Play the puzzles one after another
+pub fn puzzle(puzzles: Vec<Puzzle>, options: Options, animation: bool) -> ! {
+    drill(Pack::new(puzzles, None, options, animation))
+}
+
+// Endless perfect clear puzzles with bag-aligned queues
+pub fn perfect_clear(options: Options, animation: bool) -> ! {
+    let mut rng = ChaCha8Rng::seed_from_u64(options.seed.unwrap_or_else(rand::random));
+    let puzzles = vec![deal(&mut rng, 1)];
+    drill(Pack::new(puzzles, Some(rng), options, animation))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mino::MinoKind;
+
+    const PACK: &str = "\
+# a T-spin double
+name tsd
+goal tsd
+queue T
+XX........
+X...XXXXXX
+XX.XXXXXXX
+---
+goal lines 1
+queue IO
+XXXX..XXXX
+";
+
+    #[test]
+    fn test_puzzle() {
+        let puzzles = parse(PACK).unwrap();
+        assert_eq!(puzzles.len(), 2);
+        assert_eq!(puzzles[0].name, "tsd");
+        assert_eq!(puzzles[1].target, Target::Lines(1));
+        assert_eq!(puzzles[1].setup.minos, vec![MinoKind::I, MinoKind::O]);
+        assert!(parse("queue T\n").is_err());
+        assert!(parse("goal pc\nqueue T\nhold IJ\n").is_err());
+        assert!(parse("goal survive 3\nqueue TI\n").is_err());
+        assert!(parse("goal survive 3\nqueue TI\nhold O\n").is_ok());
+
+        let options = Options {
+            seed: Some(1),
+            ..Default::default()
+        };
+        let mut attempt = Attempt::new(&puzzles[1], options);
+        assert_eq!(attempt.game.options.next_length, 1);
+        // the I doesn't fill the gap
+        for _ in 0..3 {
+            handle_input(&mut attempt.game, Input::Right).unwrap();
+        }
+        let result = handle_input(&mut attempt.game, Input::HardDrop);
+        attempt.update(puzzles[1].target, result);
+        assert!(attempt.state == State::Playing);
+        assert_eq!(attempt.game.options.next_length, 0);
+        // the O does
+        let result = handle_input(&mut attempt.game, Input::HardDrop);
+        attempt.update(puzzles[1].target, result);
+        assert!(attempt.state == State::Solved);
+
+        let mut attempt = Attempt::new(&puzzles[1], options);
+        for _ in 0..2 {
+            handle_input(&mut attempt.game, Input::Right).unwrap();
+            let result = handle_input(&mut attempt.game, Input::HardDrop);
+            attempt.update(puzzles[1].target, result);
+        }
+        assert!(attempt.state == State::Failed("out of minos"));
+
+        // holding doesn't hide the last minos of the queue
+        let puzzle = Puzzle {
+            name: String::new(),
+            target: Target::PerfectClear,
+            setup: Setup {
+                minos: parse_minos("TIOS").unwrap(),
+                ..Default::default()
+            },
+        };
+        let mut attempt = Attempt::new(&puzzle, options);
+        assert_eq!(attempt.game.options.next_length, 3);
+        let result = handle_input(&mut attempt.game, Input::Hold);
+        attempt.update(puzzle.target, result);
+        assert_eq!(attempt.game.options.next_length, 2);
+    }
+
+    #[test]
+    fn test_pc_tries() {
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let puzzles = vec![deal(&mut rng, 1)];
+        let mut pack = Pack::new(puzzles, Some(rng), Options::default(), false);
+        // retries count as tries of their own
+        for tries in 1..=2 {
+            while pack.attempt.state == State::Playing {
+                let result = handle_input(&mut pack.attempt.game, Input::HardDrop);
+                pack.update(result);
+            }
+            assert_eq!(pack.tries, tries);
+            pack.start(pack.current);
+        }
+        assert_eq!(pack.summary(), "perfect clears: 0/2 (0%)");
+    }
+}
@@ -0,0 +1,241 @@
+use crate::stats::format_time;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{env, fs, io};
+
+pub const TOP_SCORES: usize = 10;
+
+// Best run of a mode
+#[derive(Clone, Copy)]
+pub struct Record {
+    pub frame: u64,
+    pub pieces: usize,
+}
+
+// $XDG_DATA_HOME/tetris, or ~/.local/share/tetris
+pub fn data_dir() -> Option<PathBuf> {
+    let base = match env::var_os("XDG_DATA_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".local/share"),
+    };
+    Some(base.join("tetris"))
+}
+
+fn best_path(name: &str) -> Option<PathBuf> {
+    Some(data_dir()?.join(format!("{name}.best")))
+}
+
+// A missing or broken file means there is no personal best yet
+pub fn load_best(name: &str) -> Option<Record> {
+    let text = fs::read_to_string(best_path(name)?).ok()?;
+    let mut fields = text.split_whitespace();
+    Some(Record {
+        frame: fields.next()?.parse().ok()?,
+        pieces: fields.next()?.parse().ok()?,
+    })
+}
+
+pub fn save_best(name: &str, record: &Record) -> io::Result<()> {
+    let path = best_path(name).ok_or(io::ErrorKind::NotFound)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, format!("{} {}\n", record.frame, record.pieces))
+}
+
+// One line of a high score table
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Score {
+    pub name: String,
+    pub score: usize,
+    pub lines: usize,
+    pub frame: u64, // time of the game
+    pub date: u64,  // seconds since the epoch
+    pub seed: u64,
+}
+
+// What makes a score better than another
+#[derive(Clone, Copy, PartialEq)]
+pub enum Rank {
+    Score, // higher score
+    Time,  // shorter time
+}
+
+// sprint and dig tables hold finished games, ranked by time
+pub fn rank(board: &str) -> Rank {
+    match board.split('-').next() {
+        Some("sprint" | "dig") => Rank::Time,
+        _ => Rank::Score,
+    }
+}
+
+static PLAYER_NAME: OnceLock<String> = OnceLock::new();
+
+pub fn set_player_name(name: String) {
+    PLAYER_NAME.set(name).ok();
+}
+
+// --name, or the login name
+pub fn player_name() -> String {
+    PLAYER_NAME
+        .get()
+        .cloned()
+        .or_else(|| env::var("USER").ok())
+        .unwrap_or_else(|| "player".to_string())
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn scores_dir() -> Option<PathBuf> {
+    Some(data_dir()?.join("scores"))
+}
+
+// The file of a table. Board names are made of the mode and its goal, so anything which could
+// lead out of the scores directory is refused.
+fn scores_path(board: &str) -> Option<PathBuf> {
+    let valid = !board.is_empty() && board.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+    if !valid {
+        return None;
+    }
+    Some(scores_dir()?.join(format!("{board}.json")))
+}
+
+// A missing table is empty. A broken one is moved aside to <board>.json.broken and starts over empty.
+pub fn load_scores(board: &str) -> Vec<Score> {
+    let Some(path) = scores_path(board) else {
+        return vec![];
+    };
+    let Ok(text) = fs::read_to_string(&path) else {
+        return vec![];
+    };
+    match serde_json::from_str(&text) {
+        Ok(scores) => scores,
+        Err(_) => {
+            fs::rename(&path, path.with_extension("json.broken")).ok();
+            vec![]
+        }
+    }
+}
+
+// Add the score to the table of the board.
+// Returns the table and the position of the score in it, if it made it.
+pub fn add_score(board: &str, rank: Rank, score: Score) -> (Vec<Score>, Option<usize>) {
+    let mut scores = load_scores(board);
+    let position = scores
+        .iter()
+        .position(|other| better(rank, &score, other))
+        .unwrap_or(scores.len());
+    if position >= TOP_SCORES {
+        return (scores, None);
+    }
+    scores.insert(position, score);
+    scores.truncate(TOP_SCORES);
+    if save_scores(board, &scores).is_err() {
+        return (scores, None);
+    }
+    (scores, Some(position))
+}
+
+fn better(rank: Rank, score: &Score, other: &Score) -> bool {
+    match rank {
+        Rank::Score => score.score > other.score,
+        Rank::Time => score.frame < other.frame,
+    }
+}
+
+fn save_scores(board: &str, scores: &[Score]) -> io::Result<()> {
+    let path = scores_path(board).ok_or(io::ErrorKind::InvalidInput)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    // write and rename, so that a crash in the middle can't break the table
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, serde_json::to_string_pretty(scores)? + "\n")?;
+    fs::rename(tmp, path)
+}
+
+// names of the boards which have a table
+pub fn boards() -> Vec<String> {
+    let Some(Ok(entries)) = scores_dir().map(fs::read_dir) else {
+        return vec![];
+    };
+    let mut boards = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            let board = name.strip_suffix(".json")?;
+            scores_path(board).is_some().then(|| board.to_string())
+        })
+        .collect::<Vec<_>>();
+    boards.sort();
+    boards
+}
+
+// one line of the table, the main value first
+pub fn format_score(i: usize, rank: Rank, score: &Score) -> String {
+    let value = match rank {
+        Rank::Score => score.score.to_string(),
+        Rank::Time => format_time(score.frame),
+    };
+    format!(
+        "{:2}. {:<8.8} {value:>9} {:>4} {}",
+        i + 1,
+        score.name,
+        score.lines,
+        format_date(score.date)
+    )
+}
+
+// YYYY-MM-DD in UTC
+pub fn format_date(secs: u64) -> String {
+    // days to the civil date, from http://howardhinnant.github.io/date_algorithms.html
+    let z = (secs / 86400) as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_date() {
+        let tests = [
+            (0, "1970-01-01"),
+            (951782400, "2000-02-29"),
+            (1792368000, "2026-10-19"),
+        ];
+        for (secs, expect) in tests {
+            assert_eq!(format_date(secs), expect);
+        }
+    }
+
+    #[test]
+    fn test_scores_path() {
+        assert!(scores_path("sprint-40").is_some() || data_dir().is_none());
+        for board in [
+            "",
+            "../saves/normal",
+            "../replays/1",
+            "a/b",
+            "..",
+            "normal.json",
+        ] {
+            assert!(scores_path(board).is_none(), "{board}");
+        }
+    }
+}
@@ -0,0 +1,374 @@
+use crate::ai::eval;
+use crate::game::*;
+use crate::genetic_algorigthm::GenoSeq;
+use crate::play::{Frames, Mode};
+use crate::records::data_dir;
+use crate::terminal;
+use getch_rs::{Getch, Key};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{fs, io};
+
+// Bumped whenever the format or the engine changes in a way old replays can't be played
+pub const REPLAY_VERSION: u32 = 2;
+
+// Everything needed to play a game again, see README.md
+#[derive(Serialize, Deserialize)]
+pub struct Replay {
+    pub version: u32,
+    pub mode: Mode,
+    pub options: Options,          // the seed is always set
+    pub weight: Option<GenoSeq>,   // the AI played with these weights instead of the inputs
+    pub pieces: usize,             // pieces placed when the game ended
+    pub frame: u64,                // frame when the game ended
+    pub score: usize,              // to check that the replay ends the same way
+    pub inputs: Vec<(u64, Input)>, // game frame when the input was made, and the input
+    #[serde(default)]
+    pub setup: Option<Setup>, // the position the game started from
+}
+
+impl Replay {
+    pub fn new(
+        mode: Mode,
+        game: &Game,
+        weight: Option<GenoSeq>,
+        inputs: Vec<(u64, Input)>,
+    ) -> Replay {
+        Replay {
+            version: REPLAY_VERSION,
+            mode,
+            options: Options {
+                seed: Some(game.seed),
+                ..game.options
+            },
+            weight,
+            pieces: game.stats.pieces,
+            frame: game.frame,
+            score: game.score,
+            inputs,
+            setup: None,
+        }
+    }
+
+    // $XDG_DATA_HOME/tetris/replays/<milliseconds since the epoch>.json
+    pub fn save(&self) -> io::Result<PathBuf> {
+        let dir = data_dir().ok_or(io::ErrorKind::NotFound)?.join("replays");
+        fs::create_dir_all(&dir)?;
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let path = dir.join(format!("{millis}.json"));
+        fs::write(&path, serde_json::to_string(self)? + "\n")?;
+        Ok(path)
+    }
+
+    fn start(&self) -> Game {
+        match &self.setup {
+            Some(setup) => Game::with_setup(self.options, setup)
+                .unwrap_or_else(|_| Game::with_options(self.options)),
+            None => Game::with_options(self.options),
+        }
+    }
+
+    pub fn load(path: &Path) -> io::Result<Replay> {
+        let replay: Replay = serde_json::from_str(&fs::read_to_string(path)?)?;
+        if replay.version != REPLAY_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "replay version {}, expected {REPLAY_VERSION}",
+                    replay.version
+                ),
+            ));
+        }
+        Ok(replay)
+    }
+}
+
+const SPEEDS: [f64; 6] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0];
+const AUTO_PIECE_FRAMES: u64 = 6; // the AI places a piece every this many frames at 1x
+
+// A replay being played through the engine again
+struct Playback {
+    replay: Replay,
+    game: Game,
+    next_input: usize, // index of the next input in `replay.inputs`
+    ended: bool,
+    paused: bool,
+    speed: usize,        // index in `SPEEDS`
+    elapsed: f64,        // frames to play, advanced by the speed every frame
+    seek: Option<usize>, // piece number being typed in
+}
+
+impl Playback {
+    fn new(replay: Replay) -> Playback {
+        Playback {
+            game: replay.start(),
+            replay,
+            next_input: 0,
+            ended: false,
+            paused: false,
+            speed: 2,
+            elapsed: 0.0,
+            seek: None,
+        }
+    }
+
+    fn rewind(&mut self) {
+        self.game = self.replay.start();
+        self.next_input = 0;
+        self.ended = false;
+    }
+
+    // Advance one frame, the same way `play::normal` does: the inputs of the frame, then the tick.
+    // The AI places one piece instead.
+    fn step(&mut self) {
+        if self.ended {
+            return;
+        }
+        let result = match self.replay.weight {
+            Some(weight) => self.place(&weight),
+            None => self.play_frame(),
+        };
+        self.game.events.clear();
+        if result.is_err() || goal_reached(&self.game) {
+            self.ended = true;
+        }
+    }
+
+    fn play_frame(&mut self) -> Result<(), ()> {
+        while let Some(&(frame, input)) = self.replay.inputs.get(self.next_input) {
+            if frame > self.game.frame {
+                break;
+            }
+            self.next_input += 1;
+            handle_input(&mut self.game, input)?;
+            if goal_reached(&self.game) {
+                return Ok(());
+            }
+        }
+        if self.game.frame >= self.replay.frame {
+            self.ended = true;
+            return Ok(());
+        }
+        tick(&mut self.game).map(|_| ())
+    }
+
+    fn place(&mut self, weight: &GenoSeq) -> Result<(), ()> {
+        if self.game.stats.pieces >= self.replay.pieces {
+            self.ended = true;
+            return Ok(());
+        }
+        self.game = eval(&self.game, weight);
+        landing(&mut self.game)
+    }
+
+    // play from the start until `pieces` pieces are placed
+    fn seek(&mut self, pieces: usize) {
+        self.rewind();
+        while !self.ended && self.game.stats.pieces < pieces {
+            self.step();
+        }
+    }
+
+    // called every real frame
+    fn update(&mut self) {
+        if self.paused || self.ended {
+            return;
+        }
+        self.elapsed += SPEEDS[self.speed];
+        let frames_per_step = match self.replay.weight {
+            Some(_) => AUTO_PIECE_FRAMES as f64,
+            None => 1.0,
+        };
+        while self.elapsed >= frames_per_step {
+            self.elapsed -= frames_per_step;
+            self.step();
+        }
+    }
+
+    fn result(&self) -> String {
+        if self.game.score == self.replay.score {
+            format!("END  score {} matches the record", self.game.score)
+        } else {
+            format!(
+                "END  score {} DIFFERS from the record {}",
+                self.game.score, self.replay.score
+            )
+        }
+    }
+
+    fn draw(&self) {
+        draw(&self.game);
+        let status = if let Some(seek) = self.seek {
+            format!("go to piece: {seek}_")
+        } else if self.ended {
+            self.result()
+        } else {
+            format!(
+                "REPLAY {}x{}",
+                SPEEDS[self.speed],
+                if self.paused { "  PAUSE" } else { "" }
+            )
+        };
+        print!(
+            "\x1b[24;1H{status}  piece {}/{}\x1b[K",
+            self.game.stats.pieces, self.replay.pieces
+        );
+        print!("\x1b[25;1Hspace: pause  . : step  - +: speed  <- ->: piece  g: go to piece  q: quit\x1b[K");
+        println!();
+    }
+}
+
+// Play a replay through the engine again
+pub fn playback(replay: Replay) -> ! {
+    let playback = Arc::new(Mutex::new(Playback::new(replay)));
+    playback.lock().unwrap().draw();
+
+    {
+        let playback = Arc::clone(&playback);
+
+        let _ = thread::spawn(move || {
+            let mut frames = Frames::new();
+            loop {
+                frames.wait();
+                let mut playback = playback.lock().unwrap();
+                if playback.paused || playback.ended {
+                    continue;
+                }
+                playback.update();
+                playback.draw();
+            }
+        });
+    }
+
+    let g = Getch::new();
+    loop {
+        let key = g.getch();
+        let mut playback = playback.lock().unwrap();
+        if let Ok(Key::Char('q') | Key::Ctrl('c')) = key {
+            let result = playback.ended.then(|| playback.result());
+            terminal::restore();
+            if let Some(result) = result {
+                println!("{result}");
+            }
+            quit();
+        }
+
+        if let Some(seek) = playback.seek {
+            match key {
+                Ok(Key::Char(c)) if c.is_ascii_digit() => {
+                    let digit = c.to_digit(10).unwrap() as usize;
+                    playback.seek = Some(seek.saturating_mul(10).saturating_add(digit));
+                }
+                Ok(Key::Backspace) => playback.seek = Some(seek / 10),
+                Ok(Key::Char('\n' | '\r')) => {
+                    playback.seek = None;
+                    playback.seek(seek);
+                }
+                Ok(Key::Esc) => playback.seek = None,
+                _ => continue,
+            }
+            playback.draw();
+            continue;
+        }
+
+        match key {
+            Ok(Key::Char(' ')) => playback.paused = !playback.paused,
+            Ok(Key::Char('.')) => {
+                playback.paused = true;
+                playback.step();
+            }
+            Ok(Key::Char('-')) => playback.speed = playback.speed.saturating_sub(1),
+            Ok(Key::Char('+' | '=')) => playback.speed = (playback.speed + 1).min(SPEEDS.len() - 1),
+            Ok(Key::Left) => {
+                let pieces = playback.game.stats.pieces;
+                playback.seek(pieces.saturating_sub(1));
+            }
+            Ok(Key::Right) => {
+                let pieces = playback.game.stats.pieces;
+                playback.seek(pieces + 1);
+            }
+            Ok(Key::Char('g')) => playback.seek = Some(0),
+            _ => continue,
+        }
+        playback.draw();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::DEFAULT_WEIGHT;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    const INPUTS: [Input; 7] = [
+        Input::Left,
+        Input::Right,
+        Input::SoftDrop,
+        Input::HardDrop,
+        Input::RotateRight,
+        Input::RotateLeft,
+        Input::Hold,
+    ];
+
+    #[test]
+    fn test_playback_same_game() {
+        // played like `play::normal`: inputs between the ticks
+        let mut game = Game::with_options(Options {
+            seed: Some(7),
+            ..Default::default()
+        });
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let mut inputs = vec![];
+        for _ in 0..FPS * 60 {
+            if rng.gen_ratio(1, 8) {
+                let input = INPUTS[rng.gen_range(0..INPUTS.len())];
+                inputs.push((game.frame, input));
+                if handle_input(&mut game, input).is_err() {
+                    break;
+                }
+            }
+            if tick(&mut game).is_err() {
+                break;
+            }
+        }
+        assert!(game.stats.pieces > 10);
+
+        let mut playback = Playback::new(Replay::new(Mode::Normal, &game, None, inputs));
+        while !playback.ended {
+            playback.step();
+        }
+        assert_eq!(playback.game.field, game.field);
+        assert_eq!(playback.game.score, game.score);
+        assert_eq!(playback.game.frame, game.frame);
+
+        playback.seek(5);
+        assert_eq!(playback.game.stats.pieces, 5);
+    }
+
+    #[test]
+    fn test_playback_auto() {
+        let mut game = Game::with_options(Options {
+            seed: Some(7),
+            ..Default::default()
+        });
+        for _ in 0..30 {
+            game = eval(&game, &DEFAULT_WEIGHT);
+            landing(&mut game).unwrap();
+        }
+
+        let replay = Replay::new(Mode::Normal, &game, Some(DEFAULT_WEIGHT), vec![]);
+        let mut playback = Playback::new(replay);
+        while !playback.ended {
+            playback.step();
+        }
+        assert_eq!(playback.game.field, game.field);
+        assert_eq!(playback.game.score, game.score);
+    }
+}
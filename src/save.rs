@@ -0,0 +1,150 @@
+use crate::game::*;
+use crate::mino::{is_rotation_of, MINO_KINDS};
+use crate::play::Mode;
+use crate::records::data_dir;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+
+// Bumped whenever `Game` changes in a way old saves can't be loaded
+pub const SAVE_VERSION: u32 = 1;
+
+// A game suspended in the middle, RNG states included
+#[derive(Serialize, Deserialize)]
+pub struct Save {
+    pub version: u32,
+    pub mode: Mode,
+    pub game: Game,
+    pub inputs: Vec<(u64, Input)>, // so that the replay of the resumed game is still complete
+    #[serde(default)]
+    pub setup: Option<Setup>, // the position the game started from
+}
+
+impl Save {
+    // $XDG_DATA_HOME/tetris/saves/<mode>.json, one save for each mode
+    pub fn path(mode: Mode) -> Option<PathBuf> {
+        Some(
+            data_dir()?
+                .join("saves")
+                .join(format!("{}.json", mode.name())),
+        )
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string(self)? + "\n")
+    }
+
+    pub fn load(path: &Path) -> io::Result<Save> {
+        let save: Save = serde_json::from_str(&fs::read_to_string(path)?)?;
+        if save.version != SAVE_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("save version {}, expected {SAVE_VERSION}", save.version),
+            ));
+        }
+        save.check()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(save)
+    }
+
+    // The file may have been edited or cut short, so check what the game relies on
+    fn check(&self) -> Result<(), String> {
+        let game = &self.game;
+        let minos = [game.mino]
+            .iter()
+            .chain(&game.hold)
+            .chain(&game.next)
+            .chain(&game.next_buf)
+            .all(|mino| MINO_KINDS.iter().any(|&kind| is_rotation_of(mino, kind)));
+        if !minos {
+            return Err("unknown mino".into());
+        }
+        let inside = game.pos.x < FIELD_WIDTH
+            && game.pos.y < FIELD_HEIGHT
+            && mino_cells(&game.mino, &game.pos)
+                .iter()
+                .all(|&(x, y)| x < FIELD_WIDTH && y < FIELD_HEIGHT);
+        if !is_valid_field(&game.field)
+            || !inside
+            || is_collision(&game.field, &game.pos, &game.mino)
+        {
+            return Err("broken field".into());
+        }
+        if game.next.is_empty() || game.next.len() < game.options.next_length {
+            return Err("too few next minos".into());
+        }
+        if game.options.next_length > NEXT_LENGTH_MAX || game.options.start_level == 0 {
+            return Err("broken options".into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_and_load() {
+        let mut game = Game::with_options(Options {
+            seed: Some(3),
+            ..Default::default()
+        });
+        for _ in 0..5 {
+            handle_input(&mut game, Input::HardDrop).unwrap();
+        }
+        receive_garbage(&mut game, 2);
+
+        let path = std::env::temp_dir().join(format!("tetris-save-{}.json", std::process::id()));
+        let save = Save {
+            version: SAVE_VERSION,
+            mode: Mode::Marathon,
+            game: game.clone(),
+            inputs: vec![(0, Input::HardDrop)],
+            setup: None,
+        };
+        save.save(&path).unwrap();
+        let mut loaded = Save::load(&path).unwrap().game;
+        fs::remove_file(&path).ok();
+
+        assert_eq!(loaded.field, game.field);
+        assert_eq!(loaded.next, game.next);
+        assert_eq!(incoming_lines(&loaded), 2);
+        // the RNGs go on the same way
+        for _ in 0..20 {
+            handle_input(&mut game, Input::HardDrop).ok();
+            handle_input(&mut loaded, Input::HardDrop).ok();
+        }
+        assert_eq!(loaded.field, game.field);
+        assert_eq!(loaded.next, game.next);
+
+        // broken saves are refused instead of crashing the game later
+        let breaks: [fn(&mut Game); 4] = [
+            |game| game.next.clear(),
+            |game| game.field[5][5] = 99,
+            |game| game.field[5][1] = 0,
+            |game| game.options.start_level = 0,
+        ];
+        for (i, broken) in breaks.iter().enumerate() {
+            let mut save = Save {
+                version: SAVE_VERSION,
+                mode: Mode::Marathon,
+                game: game.clone(),
+                inputs: vec![],
+                setup: None,
+            };
+            broken(&mut save.game);
+            save.save(&path).unwrap();
+            let error = Save::load(&path).err();
+            assert_eq!(
+                error.map(|e| e.kind()),
+                Some(io::ErrorKind::InvalidData),
+                "{i}"
+            );
+        }
+        fs::remove_file(&path).ok();
+    }
+}
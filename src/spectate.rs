@@ -0,0 +1,225 @@
+use crate::block::COLOR_TABLE;
+use crate::game::*;
+use crate::mino::MinoShape;
+use crate::play::FRAME;
+use crate::stats::Stats;
+use crate::terminal;
+use getch_rs::{Getch, Key};
+use serde::{Deserialize, Serialize};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Instant;
+
+pub const DEFAULT_PORT: u16 = 7879;
+
+// What spectators see of a game. The random generators and the minos after the visible next ones
+// are left out, so that nobody can tell the minos to come.
+#[derive(Serialize, Deserialize)]
+struct View {
+    field: FieldSize,
+    pos: Position,
+    mino: MinoShape,
+    hold: Option<MinoShape>,
+    next: Vec<MinoShape>,
+    score: usize,
+    total_line: usize,
+    frame: u64,
+    stats: Stats,
+    options: Options,
+    incoming: usize, // garbage lines
+    combo: usize,
+    b2b: bool,
+}
+
+impl View {
+    fn new(game: &Game) -> View {
+        View {
+            field: game.field,
+            pos: game.pos,
+            mino: game.mino,
+            hold: game.hold,
+            next: game
+                .next
+                .iter()
+                .take(game.options.next_length)
+                .copied()
+                .collect(),
+            score: game.score,
+            total_line: game.total_line,
+            frame: game.frame,
+            stats: game.stats.clone(),
+            options: Options {
+                seed: None,
+                ..game.options
+            },
+            incoming: incoming_lines(game),
+            combo: game.combo,
+            b2b: game.b2b,
+        }
+    }
+
+    // The view comes from the network, so check that it can be drawn: known blocks, the walls
+    // where they belong and the mino inside the field
+    fn is_valid(&self) -> bool {
+        let minos = [self.mino]
+            .iter()
+            .chain(self.hold.iter())
+            .chain(self.next.iter())
+            .flatten()
+            .flatten()
+            .all(|&block| block < COLOR_TABLE.len());
+        let inside = mino_cells(&self.mino, &self.pos)
+            .iter()
+            .all(|&(x, y)| x < FIELD_WIDTH && y < FIELD_HEIGHT);
+        minos
+            && is_valid_field(&self.field)
+            && inside
+            && !is_collision(&self.field, &self.pos, &self.mino)
+            && self.next.len() <= NEXT_LENGTH_MAX
+            && self.options.next_length <= NEXT_LENGTH_MAX
+    }
+
+    // a game to draw
+    fn game(self) -> Game {
+        let mut game = Game::with_options(self.options);
+        game.field = self.field;
+        game.pos = self.pos;
+        game.mino = self.mino;
+        game.hold = self.hold;
+        game.next = self.next.into();
+        game.score = self.score;
+        game.total_line = self.total_line;
+        game.frame = self.frame;
+        game.stats = self.stats;
+        game.incoming = [Garbage {
+            lines: self.incoming,
+            hole: 0,
+        }]
+        .into();
+        game.combo = self.combo;
+        game.b2b = self.b2b;
+        game
+    }
+}
+
+// The game is sent to every spectator as one line of JSON
+struct Broadcast {
+    sender: SyncSender<String>,
+    last: Option<Instant>, // when the game was last sent
+}
+
+static BROADCAST: Mutex<Option<Broadcast>> = Mutex::new(None);
+
+// Start accepting spectators on this machine, `publish` does nothing until this is called
+pub fn broadcast(port: u16) -> io::Result<()> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    let spectators = Arc::new(Mutex::new(Vec::<TcpStream>::new()));
+
+    {
+        let spectators = Arc::clone(&spectators);
+        let _ = thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                stream.set_nodelay(true).ok();
+                spectators.lock().unwrap().push(stream);
+            }
+        });
+    }
+
+    // slow spectators must not slow the game down, so games are dropped while one is being sent
+    let (sender, receiver) = mpsc::sync_channel::<String>(1);
+    let _ = thread::spawn(move || {
+        for line in receiver {
+            spectators
+                .lock()
+                .unwrap()
+                .retain_mut(|stream| writeln!(stream, "{line}").is_ok());
+        }
+    });
+
+    *BROADCAST.lock().unwrap() = Some(Broadcast { sender, last: None });
+    Ok(())
+}
+
+// Send the game to the spectators, at most once a frame
+pub fn publish(game: &Game) {
+    let mut broadcast = BROADCAST.lock().unwrap();
+    let Some(broadcast) = broadcast.as_mut() else {
+        return;
+    };
+    if broadcast.last.is_some_and(|last| last.elapsed() < FRAME) {
+        return;
+    }
+    broadcast.last = Some(Instant::now());
+    if let Ok(line) = serde_json::to_string(&View::new(game)) {
+        broadcast.sender.try_send(line).ok();
+    }
+}
+
+// Draw the games coming from a broadcast until it ends
+pub fn spectate(stream: TcpStream) -> ! {
+    println!("\x1b[2J\x1b[2;1Hwaiting for the game...");
+
+    let _ = thread::spawn(move || {
+        let mut game = None;
+        for line in BufReader::new(stream).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            // a line that doesn't parse or can't be drawn is skipped, the next one replaces it anyway
+            let view = serde_json::from_str::<View>(&line).ok();
+            if let Some(next) = view.filter(View::is_valid).map(View::game) {
+                draw(&next);
+                game = Some(next);
+            }
+        }
+        terminal::restore();
+        match game {
+            Some(game) => println!(
+                "The broadcast ended. score: {}, {} lines",
+                game.score, game.total_line
+            ),
+            None => println!("The broadcast ended"),
+        }
+        quit();
+    });
+
+    let g = Getch::new();
+    loop {
+        if let Ok(Key::Char('q') | Key::Ctrl('c')) = g.getch() {
+            quit();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::block_kind;
+
+    #[test]
+    fn test_view() {
+        let game = Game::with_options(Options {
+            seed: Some(1),
+            ..Default::default()
+        });
+        let line = serde_json::to_string(&View::new(&game)).unwrap();
+        assert!(!line.contains("rng"));
+        let view: View = serde_json::from_str(&line).unwrap();
+        assert!(view.is_valid());
+        assert_eq!(view.next.len(), NEXT_LENGTH);
+        assert_eq!(view.options.seed, None);
+
+        let mut view = View::new(&game);
+        view.pos = Position { x: 12, y: 0 };
+        assert!(!view.is_valid());
+        let mut view = View::new(&game);
+        view.field[FIELD_HEIGHT - 2][5] = block_kind::NONE;
+        assert!(!view.is_valid());
+        let mut view = View::new(&game);
+        view.field[5][5] = 99;
+        assert!(!view.is_valid());
+    }
+}
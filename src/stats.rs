@@ -0,0 +1,85 @@
+use crate::block::{block_kind, BlockColor};
+use crate::game::FPS;
+use serde::{Deserialize, Serialize};
+
+pub const PIECE_NAMES: [&str; 7] = ["I", "O", "S", "Z", "J", "L", "T"];
+
+// Counters updated by the engine while playing
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Stats {
+    pub pieces: usize,          // pieces placed
+    pub attack: usize,          // garbage lines sent
+    pub tetrises: usize,        // 4 lines clears
+    pub garbage_cleared: usize, // cleared lines which were garbage
+    pub received: [usize; 7],   // pieces received, in the order of `PIECE_NAMES`
+    #[serde(default)]
+    pub perfect_clears: usize,
+}
+
+impl Stats {
+    pub fn receive(&mut self, block: BlockColor) {
+        if let Some(count) = self.received.get_mut(block.wrapping_sub(block_kind::I)) {
+            *count += 1;
+        }
+    }
+
+    // pieces per second
+    pub fn pps(&self, frame: u64) -> f64 {
+        per_second(self.pieces, frame)
+    }
+
+    // lines per minute
+    pub fn lpm(&self, lines: usize, frame: u64) -> f64 {
+        per_second(lines, frame) * 60.0
+    }
+
+    // attack per minute
+    pub fn apm(&self, frame: u64) -> f64 {
+        per_second(self.attack, frame) * 60.0
+    }
+
+    // percentage of the lines cleared by tetrises
+    pub fn tetris_rate(&self, lines: usize) -> f64 {
+        if lines == 0 {
+            return 0.0;
+        }
+        (self.tetrises * 4) as f64 / lines as f64 * 100.0
+    }
+}
+
+fn per_second(count: usize, frame: u64) -> f64 {
+    if frame == 0 {
+        return 0.0;
+    }
+    count as f64 / (frame as f64 / FPS as f64)
+}
+
+// m:ss.cc
+pub fn format_time(frame: u64) -> String {
+    let centi = frame * 100 / FPS;
+    format!(
+        "{}:{:02}.{:02}",
+        centi / 6000,
+        centi / 100 % 60,
+        centi % 100
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_time() {
+        let tests = [
+            (0, "0:00.00"),
+            (30, "0:00.50"),
+            (FPS * 59 + 59, "0:59.98"),
+            (FPS * 61, "1:01.00"),
+            (FPS * 600, "10:00.00"),
+        ];
+        for (frame, expect) in tests {
+            assert_eq!(format_time(frame), expect);
+        }
+    }
+}
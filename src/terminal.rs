@@ -0,0 +1,112 @@
+use nix::sys::termios::{self, LocalFlags, SetArg, Termios};
+use signal_hook::consts::{SIGHUP, SIGINT, SIGQUIT, SIGTERM};
+use signal_hook::iterator::Signals;
+use std::io::Write;
+use std::sync::{Mutex, Once};
+use std::thread;
+
+// escape sequences
+const ALT_SCREEN_ENTER: &str = "\x1b[?1049h";
+const ALT_SCREEN_LEAVE: &str = "\x1b[?1049l";
+const CURSOR_HIDE: &str = "\x1b[?25l";
+const CURSOR_SHOW: &str = "\x1b[?25h";
+const CLEAR: &str = "\x1b[2J\x1b[H";
+const RESET_COLOR: &str = "\x1b[0m";
+
+// terminal state saved by the active session
+struct Saved {
+    termios: Option<Termios>,
+    alternate: bool,
+}
+
+static SAVED: Mutex<Option<Saved>> = Mutex::new(None);
+static HOOKS: Once = Once::new();
+
+// RAII terminal session.
+// Dropping it (or calling `restore`) puts the terminal back the way it was found.
+pub struct Terminal {
+    _private: (),
+}
+
+impl Terminal {
+    // Full screen session on the alternate screen buffer
+    pub fn new() -> Terminal {
+        Terminal::enter(true)
+    }
+
+    // Raw input only, output stays on the main screen (e.g. learning logs)
+    pub fn raw() -> Terminal {
+        Terminal::enter(false)
+    }
+
+    fn enter(alternate: bool) -> Terminal {
+        install_hooks();
+
+        // stdin may not be a tty (e.g. piped), in that case there is nothing to restore
+        let termios = termios::tcgetattr(0).ok();
+        if let Some(orig) = &termios {
+            let mut raw = orig.clone();
+            raw.local_flags
+                .remove(LocalFlags::ICANON | LocalFlags::ECHO | LocalFlags::ISIG);
+            termios::tcsetattr(0, SetArg::TCSADRAIN, &raw).ok();
+        }
+        *SAVED.lock().unwrap_or_else(|e| e.into_inner()) = Some(Saved { termios, alternate });
+
+        let mut stdout = std::io::stdout().lock();
+        if alternate {
+            write!(stdout, "{ALT_SCREEN_ENTER}{CLEAR}").ok();
+        }
+        write!(stdout, "{CURSOR_HIDE}").ok();
+        stdout.flush().ok();
+
+        Terminal { _private: () }
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        restore();
+    }
+}
+
+// Restore cursor, colors, screen buffer and echo.
+// Safe to call more than once, only the first call after `Terminal::new` does anything.
+pub fn restore() {
+    let saved = SAVED.lock().unwrap_or_else(|e| e.into_inner()).take();
+    let Some(Saved { termios, alternate }) = saved else {
+        return;
+    };
+
+    let mut stdout = std::io::stdout().lock();
+    write!(stdout, "{RESET_COLOR}{CURSOR_SHOW}").ok();
+    if alternate {
+        write!(stdout, "{ALT_SCREEN_LEAVE}").ok();
+    }
+    stdout.flush().ok();
+
+    if let Some(termios) = termios {
+        termios::tcsetattr(0, SetArg::TCSADRAIN, &termios).ok();
+    }
+}
+
+fn install_hooks() {
+    HOOKS.call_once(|| {
+        // a panic in any thread (e.g. gravity) restores the terminal and ends the game
+        let default_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            restore();
+            default_hook(info);
+            std::process::exit(101);
+        }));
+
+        // ISIG is off while playing, but signals can still come from outside (kill, hangup)
+        if let Ok(mut signals) = Signals::new([SIGHUP, SIGINT, SIGQUIT, SIGTERM]) {
+            thread::spawn(move || {
+                if let Some(signal) = signals.forever().next() {
+                    restore();
+                    std::process::exit(128 + signal);
+                }
+            });
+        }
+    });
+}
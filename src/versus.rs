@@ -0,0 +1,537 @@
+use crate::ai::eval;
+use crate::animation::Animation;
+use crate::game::*;
+use crate::genetic_algorigthm::GenoSeq;
+use crate::mino::MinoShape;
+use crate::net::{Message, Remote};
+use crate::play::{self, draw_message, Frames, COUNTDOWN_FRAMES};
+use crate::terminal;
+use getch_rs::{Getch, Key};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+// left edge of each field
+pub const PLAYER_COLS: [usize; 2] = [0, 48];
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Menu,
+    Countdown(u64),
+    Playing,
+    Over,
+}
+
+pub struct Player {
+    pub game: Game,
+    pub anim: Animation,
+    pub lost: bool,
+}
+
+// Computer player in place of player 2
+#[derive(Clone, Copy)]
+pub struct Bot {
+    pub pps: f64, // pieces per second
+    pub weight: GenoSeq,
+}
+
+// Where the bot places its current mino, worked out without holding the lock of the game
+pub struct Pick {
+    round: u64,
+    pieces: usize, // placed before the mino, to tell whether the pick is still for the current one
+    hold: bool,
+    mino: MinoShape,
+    pos: Position,
+}
+
+// Who plays player 2
+pub enum Opponent {
+    Human,
+    Bot(Bot),
+    // player 2 is a copy of the remote game, driven by its messages
+    Remote(Remote),
+}
+
+// Two games exchanging garbage
+pub struct Versus {
+    pub players: [Player; 2],
+    pub wins: [usize; 2],
+    pub opponent: Opponent,
+    pub ready: [bool; 2], // ready for the next round
+    pub round: u64,
+    pub pick: Option<Pick>, // of the bot
+}
+
+impl Versus {
+    // both players get the same minos
+    pub fn new(options: Options, animation: bool, opponent: Opponent) -> Versus {
+        let options = Options {
+            seed: Some(round_seed(options.seed, 0)),
+            ..options
+        };
+        let remote = matches!(opponent, Opponent::Remote(_));
+        Versus {
+            players: [0, 1].map(|_| Player {
+                game: Game::with_options(options),
+                anim: Animation::new(animation),
+                lost: false,
+            }),
+            wins: [0, 0],
+            opponent,
+            ready: [false, !remote],
+            round: 0,
+            pick: None,
+        }
+    }
+
+    // the next round, with new minos
+    pub fn restart(&mut self, options: Options) {
+        self.round += 1;
+        self.pick = None;
+        let options = Options {
+            seed: Some(round_seed(options.seed, self.round)),
+            ..options
+        };
+        for player in self.players.iter_mut() {
+            player.game = Game::with_options(options);
+            player.anim.clear();
+            player.lost = false;
+        }
+    }
+
+    // Mark player `i` ready for the next round.
+    // Returns true, and starts waiting again, once both are.
+    pub fn ready(&mut self, i: usize) -> bool {
+        self.ready[i] = true;
+        if self.ready.contains(&false) {
+            return false;
+        }
+        self.ready = [false, !matches!(self.opponent, Opponent::Remote(_))];
+        true
+    }
+
+    // Operate the game of player `i`.
+    // Returns true when the round is over.
+    pub fn input(&mut self, i: usize, input: Input) -> bool {
+        let holded = self.players[i].game.holded;
+        let result = handle_input(&mut self.players[i].game, input);
+        if let Opponent::Remote(remote) = &self.opponent {
+            if !holded && self.players[i].game.holded {
+                remote.send(&Message::Hold);
+            }
+        }
+        self.update(i, result)
+    }
+
+    // Send the attack of the player to the opponent and animate the events.
+    // Returns true when the round is over.
+    pub fn update(&mut self, i: usize, result: Result<(), ()>) -> bool {
+        if result.is_err() {
+            self.players[i].lost = true;
+        }
+        let events = std::mem::take(&mut self.players[i].game.events);
+        let remote = match &self.opponent {
+            Opponent::Remote(remote) => Some(remote),
+            _ => None,
+        };
+        if let (Some(remote), 0) = (remote, i) {
+            send_events(remote, &self.players[0].game, &events, result);
+        }
+        for event in &events {
+            match event {
+                // the attack of the remote player arrives as `ATTACK`
+                Event::Attack(_) if remote.is_some() && i == 1 => (),
+                Event::Attack(lines) => receive_garbage(&mut self.players[1 - i].game, *lines),
+                _ => (),
+            }
+        }
+        self.players[i].anim.push(events);
+
+        let over = self.is_over();
+        if over && !self.players[1 - i].lost {
+            self.wins[1 - i] += 1;
+        }
+        over
+    }
+
+    // Apply a message of the remote player to its copy of the game.
+    // Returns true when the round is over.
+    pub fn receive(&mut self, message: Message) -> bool {
+        let game = &mut self.players[1].game;
+        match message {
+            Message::Hold => hold(game),
+            // a lock into the walls or the blocks can't come from a real game
+            Message::Lock { mino, pos, .. } if is_collision(&game.field, &pos, &mino) => {}
+            Message::Lock { mino, pos, tspin } => {
+                game.mino = mino;
+                game.pos = pos;
+                game.rotated = tspin;
+                // the field comes with `FIELD` and the game over with `LOSE`
+                landing(game).ok();
+            }
+            Message::Field(field) => game.field = *field,
+            Message::Attack(lines) => receive_garbage(&mut self.players[0].game, lines),
+            Message::Lose => return self.update(1, Err(())),
+            Message::Hello(_) | Message::Options { .. } | Message::Ready => return false,
+        }
+        self.update(1, Ok(()))
+    }
+
+    // advance player `i` by one frame, the bot places a mino instead of letting it fall
+    pub fn tick(&mut self, i: usize) -> Result<bool, ()> {
+        let game = &mut self.players[i].game;
+        let bot = match &self.opponent {
+            Opponent::Bot(bot) if i == 1 => *bot,
+            // the remote game moves by itself
+            Opponent::Remote(_) if i == 1 => {
+                game.frame += 1;
+                return Ok(false);
+            }
+            _ => return tick(game),
+        };
+        game.frame += 1;
+        game.fall_frame += 1;
+        if (game.fall_frame as f64) < FPS as f64 / bot.pps {
+            return Ok(false);
+        }
+        // the bot thread may still be thinking
+        let Some(pick) = self
+            .pick
+            .take_if(|pick| pick.round == self.round && pick.pieces == game.stats.pieces)
+        else {
+            return Ok(false);
+        };
+        if pick.hold {
+            hold(game);
+        }
+        game.mino = pick.mino;
+        game.pos = pick.pos;
+        landing(game)?;
+        Ok(true)
+    }
+
+    // the game for the bot to think about, None when it has a pick for the current mino
+    fn bot_game(&self) -> Option<(u64, Game)> {
+        if !matches!(self.opponent, Opponent::Bot(_)) {
+            return None;
+        }
+        let game = &self.players[1].game;
+        let picked = self
+            .pick
+            .as_ref()
+            .is_some_and(|pick| pick.round == self.round && pick.pieces == game.stats.pieces);
+        (!picked).then(|| (self.round, game.clone()))
+    }
+
+    // keep what `eval` picked for `game`, unless a new round started meanwhile
+    fn set_pick(&mut self, round: u64, game: &Game, picked: &Game) {
+        if round != self.round {
+            return;
+        }
+        self.pick = Some(Pick {
+            round,
+            pieces: game.stats.pieces,
+            hold: picked.holded && !game.holded,
+            mino: picked.mino,
+            pos: picked.pos,
+        });
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.players.iter().any(|player| player.lost)
+    }
+
+    pub fn draw(&self) {
+        for (player, col) in self.players.iter().zip(PLAYER_COLS) {
+            player.anim.draw_at(&player.game, col, Hud::Compact);
+        }
+        print!("\x1b[25;1HWINS {} - {}\x1b[K", self.wins[0], self.wins[1]);
+        println!();
+    }
+
+    // result of the round on each field
+    pub fn draw_result(&self, footer: &str) {
+        self.draw();
+        for (player, col) in self.players.iter().zip(PLAYER_COLS) {
+            let text = if player.lost { " LOSE " } else { " WIN " };
+            print!("\x1b[11;{}H\x1b[1m{text}\x1b[0m", col + 9);
+        }
+        print!("\x1b[26;1H{footer}\x1b[K");
+        println!();
+    }
+
+    pub fn is_animating(&self) -> bool {
+        self.players.iter().any(|player| player.anim.is_active())
+    }
+
+    pub fn tick_animation(&mut self) {
+        for player in self.players.iter_mut() {
+            player.anim.tick();
+        }
+    }
+}
+
+// A given seed changes every round, so that both sides of a network game agree on it
+fn round_seed(seed: Option<u64>, round: u64) -> u64 {
+    seed.map_or_else(rand::random, |seed| seed.wrapping_add(round))
+}
+
+// tell the remote player what happened in the local game
+fn send_events(remote: &Remote, game: &Game, events: &[Event], result: Result<(), ()>) {
+    for event in events {
+        match event {
+            Event::Lock { mino, pos, tspin } => {
+                remote.send(&Message::Lock {
+                    mino: *mino,
+                    pos: *pos,
+                    tspin: *tspin,
+                });
+                remote.send(&Message::Field(Box::new(game.field)));
+            }
+            Event::Attack(lines) => remote.send(&Message::Attack(*lines)),
+            _ => (),
+        }
+    }
+    if result.is_err() {
+        remote.send(&Message::Lose);
+    }
+}
+
+// player 1 on the left side of the keyboard, player 2 on the right
+fn key_input(key: &Key) -> Option<(usize, Input)> {
+    match key {
+        Key::Char('a') => Some((0, Input::Left)),
+        Key::Char('d') => Some((0, Input::Right)),
+        Key::Char('s') => Some((0, Input::SoftDrop)),
+        Key::Char(' ') => Some((0, Input::HardDrop)),
+        Key::Char('w') => Some((0, Input::RotateRight)),
+        Key::Char('z') => Some((0, Input::RotateLeft)),
+        Key::Char('c') => Some((0, Input::Hold)),
+        Key::Left => Some((1, Input::Left)),
+        Key::Right => Some((1, Input::Right)),
+        Key::Down => Some((1, Input::SoftDrop)),
+        Key::Char('\n' | '\r') => Some((1, Input::HardDrop)),
+        Key::Up => Some((1, Input::RotateRight)),
+        Key::Char(',') => Some((1, Input::RotateLeft)),
+        Key::Char('.') => Some((1, Input::Hold)),
+        _ => None,
+    }
+}
+
+// both players are ready, count down to the next round
+fn start(versus: &mut Versus, state: &mut State, options: Options) {
+    if *state == State::Over {
+        versus.restart(options);
+    }
+    *state = State::Countdown(COUNTDOWN_FRAMES);
+}
+
+fn disconnected() -> ! {
+    terminal::restore();
+    println!("The opponent left the game");
+    quit();
+}
+
+// Two players versus, on one keyboard, against the bot or over the network
+pub fn versus(options: Options, animation: bool, opponent: Opponent) -> ! {
+    let versus = Arc::new(Mutex::new((
+        Versus::new(options, animation, opponent),
+        State::Menu,
+    )));
+    draw(&versus.lock().unwrap());
+
+    if let Opponent::Remote(remote) = &mut versus.lock().unwrap().0.opponent {
+        let messages = remote.messages();
+        let versus = Arc::clone(&versus);
+
+        let _ = thread::spawn(move || {
+            for message in messages {
+                let mut guard = versus.lock().unwrap();
+                let (versus, state) = &mut *guard;
+                match (*state, message) {
+                    (State::Menu | State::Over, Message::Ready) => {
+                        if versus.ready(1) {
+                            start(versus, state, options);
+                        }
+                    }
+                    // the remote countdown may end a bit earlier
+                    (State::Countdown(_) | State::Playing, message) => {
+                        if versus.receive(message) {
+                            *state = State::Over;
+                        }
+                    }
+                    _ => continue,
+                }
+                draw(&guard);
+            }
+            disconnected();
+        });
+    }
+
+    let bot = match versus.lock().unwrap().0.opponent {
+        Opponent::Bot(bot) => Some(bot),
+        _ => None,
+    };
+    if let Some(bot) = bot {
+        let versus = Arc::clone(&versus);
+
+        // the bot thinks for long, so away from the lock which the players need every frame
+        let _ = thread::spawn(move || {
+            let mut frames = Frames::new();
+            loop {
+                frames.wait();
+                let Some((round, game)) = versus.lock().unwrap().0.bot_game() else {
+                    continue;
+                };
+                let picked = eval(&game, &bot.weight);
+                versus.lock().unwrap().0.set_pick(round, &game, &picked);
+            }
+        });
+    }
+
+    {
+        let versus = Arc::clone(&versus);
+
+        let _ = thread::spawn(move || {
+            let mut frames = Frames::new();
+            loop {
+                frames.wait();
+                let mut guard = versus.lock().unwrap();
+                let (versus, state) = &mut *guard;
+                let mut redraw = versus.is_animating();
+                match *state {
+                    State::Countdown(0) => {
+                        *state = State::Playing;
+                        redraw = true;
+                    }
+                    State::Countdown(frames) => {
+                        *state = State::Countdown(frames - 1);
+                        redraw |= frames.is_multiple_of(FPS);
+                    }
+                    State::Playing => {
+                        for i in 0..2 {
+                            let result = versus.tick(i);
+                            redraw |= result != Ok(false);
+                            if versus.update(i, result.map(|_| ())) {
+                                *state = State::Over;
+                                redraw = true;
+                                break;
+                            }
+                        }
+                    }
+                    State::Menu | State::Over => (),
+                }
+                versus.tick_animation();
+                if redraw {
+                    draw(&guard);
+                }
+            }
+        });
+    }
+
+    let g = Getch::new();
+    loop {
+        let key = g.getch();
+        if let Ok(Key::Char('q') | Key::Ctrl('c')) = key {
+            quit();
+        }
+
+        let mut guard = versus.lock().unwrap();
+        let (versus, state) = &mut *guard;
+        match (*state, key) {
+            (State::Menu, Ok(Key::Char('\n' | '\r'))) | (State::Over, Ok(Key::Char('r'))) => {
+                if versus.ready[0] {
+                    continue;
+                }
+                if let Opponent::Remote(remote) = &versus.opponent {
+                    remote.send(&Message::Ready);
+                }
+                if versus.ready(0) {
+                    start(versus, state, options);
+                }
+            }
+            (State::Playing, Ok(key)) => {
+                let input = match versus.opponent {
+                    Opponent::Human => key_input(&key),
+                    _ => play::key_input(&key).map(|input| (0, input)),
+                };
+                let Some((i, input)) = input else {
+                    continue;
+                };
+                if versus.input(i, input) {
+                    *state = State::Over;
+                }
+            }
+            _ => continue,
+        }
+        draw(&guard);
+    }
+}
+
+fn draw((versus, state): &(Versus, State)) {
+    let waiting = versus.ready[0];
+    match state {
+        State::Menu if waiting => {
+            draw_message(&["ONLINE VERSUS", "", "waiting for", "the opponent..."])
+        }
+        State::Menu if !matches!(versus.opponent, Opponent::Human) => draw_message(&[
+            match versus.opponent {
+                Opponent::Remote(_) => "ONLINE VERSUS",
+                _ => "VERSUS BOT",
+            },
+            "",
+            "enter: start",
+            "q: quit",
+            "",
+            "<- ->: move",
+            "down: soft drop",
+            "space: hard drop",
+            "up z: rotate",
+            "c: hold",
+        ]),
+        State::Menu => draw_message(&[
+            "VERSUS",
+            "",
+            "enter: start",
+            "q: quit",
+            "",
+            "      1P    2P",
+            "move  a d   <- ->",
+            "soft  s     down",
+            "hard  space enter",
+            "rot   w z   up ,",
+            "hold  c     .",
+        ]),
+        State::Countdown(frames) => {
+            versus.draw();
+            for col in PLAYER_COLS {
+                print!(
+                    "\x1b[11;{}H\x1b[1m {} \x1b[0m",
+                    col + 11,
+                    frames.div_ceil(FPS)
+                );
+            }
+            println!();
+        }
+        State::Playing => versus.draw(),
+        State::Over if versus.is_animating() => versus.draw(),
+        State::Over if waiting => versus.draw_result("waiting for the opponent...  q: quit"),
+        State::Over => versus.draw_result("r: play again  q: quit"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_receive_bad_lock() {
+        let mut versus = Versus::new(Options::default(), false, Opponent::Human);
+        // an I over the left wall, and one on top of the floor
+        for line in ["LOCK I 0f00 0 5 0", "LOCK I 0f00 2 20 0"] {
+            let message = line.parse::<Message>().unwrap();
+            assert!(!versus.receive(message));
+            assert!(versus.players[1].game.field == EMPTY_FIELD, "{line}");
+        }
+        assert!("LOCK I 000f 12 0 0".parse::<Message>().is_err());
+    }
+}